@@ -0,0 +1,76 @@
+use crate::{ray::Ray, vec::Vec3};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub const EMPTY: Aabb = Aabb {
+        min: Vec3 { x: f32::INFINITY, y: f32::INFINITY, z: f32::INFINITY },
+        max: Vec3 { x: f32::NEG_INFINITY, y: f32::NEG_INFINITY, z: f32::NEG_INFINITY },
+    };
+
+    pub fn new(a: Vec3, b: Vec3) -> Aabb { Aabb { min: a.min(b), max: a.max(b) } }
+
+    pub fn from_points<I: IntoIterator<Item = Vec3>>(points: I) -> Aabb {
+        points.into_iter().fold(Aabb::EMPTY, |acc, p| acc.grow(p))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn union(self, b: Aabb) -> Aabb {
+        Aabb { min: self.min.min(b.min), max: self.max.max(b.max) }
+    }
+
+    pub fn grow(self, p: Vec3) -> Aabb { Aabb { min: self.min.min(p), max: self.max.max(p) } }
+
+    pub fn contains(&self, p: Vec3) -> bool {
+        (0..3).all(|axis| p[axis] >= self.min[axis] && p[axis] <= self.max[axis])
+    }
+
+    pub fn center(&self) -> Vec3 { (self.min + self.max) * 0.5 }
+
+    pub fn diagonal(&self) -> Vec3 { self.max - self.min }
+
+    pub fn surface_area(&self) -> f32 {
+        if self.is_empty() {
+            return 0.;
+        }
+        let d = self.diagonal();
+        2. * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    // Slab test: returns the parametric distances at which the ray enters and exits
+    // the box, clipped to [t_min, t_max].
+    pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, f32)> {
+        let inv_direction = Vec3::ONE / ray.direction;
+        self.hit_inv(&ray.origin, &inv_direction, t_min, t_max)
+    }
+
+    #[inline]
+    pub(crate) fn hit_inv(
+        &self,
+        origin: &Vec3,
+        inv_direction: &Vec3,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<(f32, f32)> {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+        for axis in 0..3 {
+            let t0 = (self.min[axis] - origin[axis]) * inv_direction[axis];
+            let t1 = (self.max[axis] - origin[axis]) * inv_direction[axis];
+            let (t0, t1) = if t0 > t1 { (t1, t0) } else { (t0, t1) };
+            t_min = t0.max(t_min);
+            t_max = t1.min(t_max);
+            if t_max < t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+}
@@ -0,0 +1,389 @@
+use fastrand::Rng;
+
+use crate::{
+    aabb::Aabb,
+    ray::Ray,
+    shape::{HitRecord, Shape, Shapes},
+    vec::Vec3,
+};
+
+const BIN_COUNT: usize = 16;
+const MAX_LEAF_SIZE: usize = 4;
+const TRAVERSAL_COST: f32 = 1.;
+const INTERSECTION_COST: f32 = 1.;
+const MAX_SAH_DEPTH: u32 = 64;
+const STACK_SIZE: usize = 128;
+
+#[derive(Clone, Copy, Debug)]
+enum Node {
+    // the left child of an interior node always immediately follows it
+    Interior { bounds: Aabb, right: u32, axis: u8 },
+    Leaf { bounds: Aabb, first: u32, count: u32 },
+}
+
+#[derive(Debug)]
+pub struct Bvh<'a> {
+    shapes: Vec<Box<dyn Shape + 'a>>,
+    unbounded: Vec<Box<dyn Shape + 'a>>,
+    nodes: Vec<Node>,
+}
+
+struct BuildItem {
+    index: usize,
+    bounds: Aabb,
+    centroid: Vec3,
+}
+
+#[derive(Clone, Copy)]
+struct Bin {
+    bounds: Aabb,
+    count: usize,
+}
+
+impl<'a> Bvh<'a> {
+    pub fn new(shapes: Shapes<'a>) -> Bvh<'a> {
+        let mut bounded = Vec::new();
+        let mut unbounded = Vec::new();
+        let mut items = Vec::new();
+        for shape in shapes.0 {
+            match shape.bounding_box() {
+                Some(bounds) => {
+                    items.push(BuildItem {
+                        index: bounded.len(),
+                        bounds,
+                        centroid: bounds.center(),
+                    });
+                    bounded.push(Some(shape));
+                }
+                None => unbounded.push(shape),
+            }
+        }
+
+        let mut nodes = Vec::new();
+        if !items.is_empty() {
+            Self::build(&mut items, 0, 0, &mut nodes);
+        }
+
+        // reorder the shapes so that every leaf refers to a contiguous range
+        let shapes = items.iter().map(|item| bounded[item.index].take().unwrap()).collect();
+
+        Bvh { shapes, unbounded, nodes }
+    }
+
+    pub fn size(&self) -> usize { self.shapes.len() + self.unbounded.len() }
+
+    fn build(items: &mut [BuildItem], first: usize, depth: u32, nodes: &mut Vec<Node>) {
+        let bounds = items.iter().fold(Aabb::EMPTY, |acc, item| acc.union(item.bounds));
+        let leaf = Node::Leaf { bounds, first: first as u32, count: items.len() as u32 };
+
+        if items.len() <= 1 {
+            nodes.push(leaf);
+            return;
+        }
+
+        let centroid_bounds = Aabb::from_points(items.iter().map(|item| item.centroid));
+
+        // past MAX_SAH_DEPTH only median splits are made, which bounds the traversal
+        // stack size
+        let split = if depth < MAX_SAH_DEPTH {
+            Self::find_split(items, &bounds, &centroid_bounds)
+        } else {
+            None
+        };
+        let mid = match split {
+            Some((axis, position, cost)) => {
+                let leaf_cost = INTERSECTION_COST * items.len() as f32;
+                if items.len() <= MAX_LEAF_SIZE && leaf_cost <= cost {
+                    nodes.push(leaf);
+                    return;
+                }
+                let mid = partition(items, |item| item.centroid[axis] < position);
+                if mid == 0 || mid == items.len() { None } else { Some((axis, mid)) }
+            }
+            None => None,
+        };
+
+        let (axis, mid) = match mid {
+            Some(split) => split,
+            None if items.len() <= MAX_LEAF_SIZE => {
+                nodes.push(leaf);
+                return;
+            }
+            None => {
+                // all centroids coincide or SAH found nothing useful: split at the median
+                let axis = Self::largest_axis(&centroid_bounds);
+                let mid = items.len() / 2;
+                items.select_nth_unstable_by(mid, |a, b| {
+                    a.centroid[axis].total_cmp(&b.centroid[axis])
+                });
+                (axis, mid)
+            }
+        };
+
+        let index = nodes.len();
+        nodes.push(leaf); // patched below once the right child's index is known
+        let (left, right) = items.split_at_mut(mid);
+        Self::build(left, first, depth + 1, nodes);
+        let right_index = nodes.len() as u32;
+        Self::build(right, first + mid, depth + 1, nodes);
+        nodes[index] = Node::Interior { bounds, right: right_index, axis: axis as u8 };
+    }
+
+    fn largest_axis(bounds: &Aabb) -> usize {
+        let d = bounds.max - bounds.min;
+        if d.x >= d.y && d.x >= d.z {
+            0
+        } else if d.y >= d.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    // Binned surface area heuristic: returns the best (axis, split position, cost).
+    fn find_split(
+        items: &[BuildItem],
+        bounds: &Aabb,
+        centroid_bounds: &Aabb,
+    ) -> Option<(usize, f32, f32)> {
+        let parent_area = bounds.surface_area();
+        if parent_area <= 0. {
+            return None;
+        }
+
+        let mut best: Option<(usize, f32, f32)> = None;
+        for axis in 0..3 {
+            let lo = centroid_bounds.min[axis];
+            let hi = centroid_bounds.max[axis];
+            let extent = hi - lo;
+            if extent <= 0. {
+                continue;
+            }
+
+            let mut bins = [Bin { bounds: Aabb::EMPTY, count: 0 }; BIN_COUNT];
+            let scale = BIN_COUNT as f32 / extent;
+            for item in items {
+                let b = (((item.centroid[axis] - lo) * scale) as usize).min(BIN_COUNT - 1);
+                bins[b].bounds = bins[b].bounds.union(item.bounds);
+                bins[b].count += 1;
+            }
+
+            let mut right_area = [0.; BIN_COUNT];
+            let mut right_count = [0; BIN_COUNT];
+            let mut acc = Bin { bounds: Aabb::EMPTY, count: 0 };
+            for i in (1..BIN_COUNT).rev() {
+                acc.bounds = acc.bounds.union(bins[i].bounds);
+                acc.count += bins[i].count;
+                right_area[i] = acc.bounds.surface_area();
+                right_count[i] = acc.count;
+            }
+
+            let mut acc = Bin { bounds: Aabb::EMPTY, count: 0 };
+            for i in 1..BIN_COUNT {
+                acc.bounds = acc.bounds.union(bins[i - 1].bounds);
+                acc.count += bins[i - 1].count;
+                if acc.count == 0 || right_count[i] == 0 {
+                    continue;
+                }
+
+                let cost = TRAVERSAL_COST
+                    + INTERSECTION_COST
+                        * (acc.bounds.surface_area() * acc.count as f32
+                            + right_area[i] * right_count[i] as f32)
+                        / parent_area;
+                if best.is_none_or(|(_, _, c)| cost < c) {
+                    best = Some((axis, lo + i as f32 / scale, cost));
+                }
+            }
+        }
+
+        best
+    }
+}
+
+fn partition<T, F: Fn(&T) -> bool>(items: &mut [T], pred: F) -> usize {
+    let mut mid = 0;
+    for i in 0..items.len() {
+        if pred(&items[i]) {
+            items.swap(i, mid);
+            mid += 1;
+        }
+    }
+    mid
+}
+
+impl<'a> Bvh<'a> {
+    // The closest of the hits `hit_shape` finds on single shapes up to a distance,
+    // visiting only the shapes whose bounds the ray reaches.
+    fn closest<'s>(
+        &'s self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        mut hit_shape: impl FnMut(&'s dyn Shape, f32) -> Option<HitRecord<'s>>,
+    ) -> Option<HitRecord<'s>> {
+        let mut closest: Option<HitRecord> = self.unbounded.iter().fold(None, |acc, s| {
+            hit_shape(s.as_ref(), acc.map(|r| r.distance).unwrap_or(t_max)).or(acc)
+        });
+
+        if self.nodes.is_empty() {
+            return closest;
+        }
+
+        let inv_direction = Vec3::ONE / ray.direction;
+        let negative = [inv_direction.x < 0., inv_direction.y < 0., inv_direction.z < 0.];
+
+        let mut stack = [0u32; STACK_SIZE];
+        let mut stack_len = 1;
+        while stack_len > 0 {
+            stack_len -= 1;
+            let node = &self.nodes[stack[stack_len] as usize];
+            let t_max = closest.map(|r| r.distance).unwrap_or(t_max);
+
+            match *node {
+                Node::Leaf { bounds, first, count } => {
+                    if bounds.hit_inv(&ray.origin, &inv_direction, t_min, t_max).is_none() {
+                        continue;
+                    }
+                    let range = first as usize..(first + count) as usize;
+                    closest = self.shapes[range].iter().fold(closest, |acc, s| {
+                        hit_shape(s.as_ref(), acc.map(|r| r.distance).unwrap_or(t_max)).or(acc)
+                    });
+                }
+                Node::Interior { bounds, right, axis } => {
+                    if bounds.hit_inv(&ray.origin, &inv_direction, t_min, t_max).is_none() {
+                        continue;
+                    }
+                    let index = stack[stack_len];
+                    // visit the nearer child first: push the farther one underneath it
+                    let (near, far) = if negative[axis as usize] {
+                        (right, index + 1)
+                    } else {
+                        (index + 1, right)
+                    };
+                    stack[stack_len] = far;
+                    stack[stack_len + 1] = near;
+                    stack_len += 2;
+                }
+            }
+        }
+
+        closest
+    }
+}
+
+impl<'a> Shape for Bvh<'a> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.closest(ray, t_min, t_max, |s, t_max| s.hit(ray, t_min, t_max))
+    }
+
+    fn sample_hit(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        rng: &mut Rng,
+    ) -> Option<HitRecord<'_>> {
+        self.closest(ray, t_min, t_max, |s, t_max| s.sample_hit(ray, t_min, t_max, rng))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+        self.nodes.first().map(|node| match *node {
+            Node::Interior { bounds, .. } | Node::Leaf { bounds, .. } => bounds,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        material::{Lambertian, Material},
+        sampling,
+        shape::Sphere,
+    };
+
+    // An infinite horizontal plane, which has no bounding box.
+    #[derive(Debug)]
+    struct Plane {
+        y: f32,
+        material: Arc<dyn Material>,
+    }
+
+    impl Shape for Plane {
+        fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+            let t = (self.y - ray.origin.y) / ray.direction.y;
+            if !(t > t_min && t < t_max) {
+                return None;
+            }
+            let normal = Vec3 { x: 0., y: 1., z: 0. };
+            let rec = HitRecord {
+                distance: t,
+                point: ray.point_at(t),
+                normal,
+                geometric_normal: normal,
+                front_face: true,
+                u: 0.,
+                v: 0.,
+                dpdu: Vec3 { x: 1., y: 0., z: 0. },
+                dpdv: Vec3 { x: 0., y: 0., z: 1. },
+                material: &*self.material,
+            };
+            Some(rec.facing(ray))
+        }
+
+        fn bounding_box(&self) -> Option<Aabb> { None }
+    }
+
+    fn random_field(seed: u64, planes: &[f32]) -> Shapes<'static> {
+        let mut rng = Rng::with_seed(seed);
+        let material: Arc<dyn Material> = Arc::new(Lambertian(0.5.into()));
+        let mut shapes = Shapes::new();
+        for _ in 0..500 {
+            let center = Vec3 { x: rng.f32(), y: rng.f32(), z: rng.f32() } * 20. - Vec3::ONE * 10.;
+            let radius = 0.05 + rng.f32() * rng.f32() * 2.;
+            shapes.add(Sphere { center, radius, material: Arc::clone(&material) });
+        }
+        for &y in planes {
+            shapes.add(Plane { y, material: Arc::clone(&material) });
+        }
+        shapes
+    }
+
+    fn assert_same_hits(planes: &[f32]) {
+        let linear = random_field(7, planes);
+        let bvh = Bvh::new(random_field(7, planes));
+        assert_eq!(bvh.size(), linear.size());
+
+        let mut rng = Rng::with_seed(11);
+        for _ in 0..10_000 {
+            let origin = Vec3 { x: rng.f32(), y: rng.f32(), z: rng.f32() } * 30. - Vec3::ONE * 15.;
+            let ray = Ray { origin, direction: sampling::uniform_sphere(&mut rng), time: 0. };
+            let (t_min, t_max) = (0.001, if rng.bool() { f32::MAX } else { rng.f32() * 20. });
+
+            let expected = linear.hit(&ray, t_min, t_max);
+            let actual = bvh.hit(&ray, t_min, t_max);
+            match (expected, actual) {
+                (None, None) => {}
+                (Some(e), Some(a)) => {
+                    assert_eq!(e.distance.to_bits(), a.distance.to_bits(), "{ray:?}");
+                    assert_eq!(e.normal.x.to_bits(), a.normal.x.to_bits(), "{ray:?}");
+                    assert_eq!(e.normal.y.to_bits(), a.normal.y.to_bits(), "{ray:?}");
+                    assert_eq!(e.normal.z.to_bits(), a.normal.z.to_bits(), "{ray:?}");
+                }
+                (e, a) => panic!("{ray:?}: linear {:?}, bvh {:?}", e.is_some(), a.is_some()),
+            }
+        }
+    }
+
+    #[test]
+    fn matches_linear_search() { assert_same_hits(&[]); }
+
+    #[test]
+    fn matches_linear_search_with_unbounded_shapes() { assert_same_hits(&[-4., 3.5]); }
+}
@@ -18,6 +18,13 @@ impl Color {
     pub fn as_array(self) -> [u8; 3] {
         [self.r, self.g, self.b]
     }
+
+    // Encodes linear radiance with the sRGB transfer function.
+    pub fn from_linear(c: Vec3) -> Color { c.map(gamma_linear_to_srgb).into() }
+}
+
+fn gamma_linear_to_srgb(x: f32) -> f32 {
+    if x <= 0.0031308 { x * 12.92 } else { 1.055 * x.powf(1.0 / 2.4) - 0.055 }
 }
 
 impl From<Vec3> for Color {
@@ -0,0 +1,158 @@
+use std::{f32::consts::PI, path::Path};
+
+use fastrand::Rng;
+use image::ImageResult;
+
+use crate::{
+    light::{Light, LightSample},
+    ray::Ray,
+    sampling::Distribution2D,
+    shape::HitRecord,
+    transform::Transform,
+    vec::Vec3,
+};
+
+// The radiance arriving from every direction, stored as an equirectangular
+// image with +y along the top row and -z in the middle of the image. Rays that
+// escape the scene look it up, and it's importance sampled as a light based on
+// the brightness of its texels.
+#[derive(Debug)]
+pub struct EnvironmentMap {
+    width: u32,
+    height: u32,
+    texels: Vec<Vec3>,
+    // from the image's frame to the scene's
+    rotation: Transform,
+    intensity: f32,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    // Reads a linear image such as a Radiance `.hdr` file. `rotation` turns the
+    // map around the y axis, in degrees, and `intensity` scales its radiance.
+    pub fn load<P: AsRef<Path>>(path: P, rotation: f32, intensity: f32) -> ImageResult<Self> {
+        let image = image::open(path)?.into_rgb32f();
+        let (width, height) = image.dimensions();
+        let texels = image.pixels().map(|p| Vec3 { x: p[0], y: p[1], z: p[2] }).collect();
+        Ok(EnvironmentMap::new(width, height, texels, rotation, intensity))
+    }
+
+    pub fn new(width: u32, height: u32, texels: Vec<Vec3>, rotation: f32, intensity: f32) -> Self {
+        assert_eq!(texels.len(), (width * height) as usize);
+        // rows towards the poles cover less solid angle
+        let func: Vec<_> = texels
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let sin_theta = (PI * ((i as u32 / width) as f32 + 0.5) / height as f32).sin();
+                (0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z).max(0.) * sin_theta
+            })
+            .collect();
+        let distribution = Distribution2D::new(&func, width as usize, height as usize);
+        let rotation = Transform::rotate(Vec3 { x: 0., y: 1., z: 0. }, rotation);
+        EnvironmentMap { width, height, texels, rotation, intensity, distribution }
+    }
+
+    pub fn radiance(&self, direction: Vec3) -> Vec3 {
+        let (u, v) = uv(self.rotation.inverse().vector(direction).unit());
+        self.texel(u, v)
+    }
+
+    // Texels are looked up without filtering, so that the radiance matches the
+    // density it's sampled with.
+    fn texel(&self, u: f32, v: f32) -> Vec3 {
+        let x = ((u * self.width as f32) as u32).min(self.width - 1);
+        let y = ((v * self.height as f32) as u32).min(self.height - 1);
+        self.texels[(y * self.width + x) as usize] * self.intensity
+    }
+}
+
+// Image coordinates of a unit direction, with v going down from +y.
+fn uv(d: Vec3) -> (f32, f32) {
+    let u = 0.5 + d.x.atan2(-d.z) / (2. * PI);
+    let v = d.y.clamp(-1., 1.).acos() / PI;
+    (u, v)
+}
+
+impl Light for EnvironmentMap {
+    fn sample(&self, _origin: Vec3, rng: &mut Rng) -> Option<LightSample> {
+        let ((u, v), pdf) = self.distribution.sample(rng);
+        let (sin_theta, cos_theta) = (PI * v).sin_cos();
+        if pdf <= 0. || sin_theta <= 0. {
+            return None;
+        }
+        let (sin_phi, cos_phi) = (2. * PI * (u - 0.5)).sin_cos();
+        let local = Vec3 { x: sin_theta * sin_phi, y: cos_theta, z: -sin_theta * cos_phi };
+
+        Some(LightSample {
+            direction: self.rotation.vector(local),
+            distance: f32::MAX,
+            radiance: self.texel(u, v),
+            // from the image's area to solid angle
+            pdf: pdf / (2. * PI * PI * sin_theta),
+            delta: false,
+        })
+    }
+
+    fn pdf(&self, _origin: Vec3, direction: Vec3) -> f32 {
+        let local = self.rotation.inverse().vector(direction).unit();
+        // not from y, which loses precision towards the poles
+        let sin_theta = (local.x * local.x + local.z * local.z).sqrt();
+        if sin_theta <= 0. {
+            return 0.;
+        }
+        let (u, v) = uv(local);
+        self.distribution.pdf(u, v) / (2. * PI * PI * sin_theta)
+    }
+
+    fn is_hit_by(&self, _ray: &Ray, rec: Option<&HitRecord>) -> bool { rec.is_none() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A small map, brighter towards one corner, turned away from the image frame.
+    fn environment() -> EnvironmentMap {
+        let (width, height) = (8, 4);
+        let texels = (0..width * height)
+            .map(|i| {
+                let (x, y) = ((i % width) as f32, (i / width) as f32);
+                Vec3 { x: 0.1 + x * y, y: 0.5 + x, z: 1. + 0.1 * y }
+            })
+            .collect();
+        EnvironmentMap::new(width, height, texels, 30., 2.)
+    }
+
+    #[test]
+    fn pdf_matches_the_samples() {
+        let map = environment();
+        let mut rng = Rng::with_seed(5);
+        for _ in 0..1000 {
+            let sample = map.sample(Vec3::ZERO, &mut rng).unwrap();
+            assert!((sample.direction.length() - 1.).abs() < 1e-4);
+            let pdf = map.pdf(Vec3::ZERO, sample.direction);
+            assert!((pdf - sample.pdf).abs() <= 1e-3 * sample.pdf, "{pdf} != {}", sample.pdf);
+        }
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        let map = environment();
+        // midpoint rule in theta and phi: the solid angle density grows like
+        // 1 / sin(theta) towards the poles, so weight it by the cells' sin(theta)
+        let n = 400;
+        let (d_theta, d_phi) = (PI / n as f32, PI / n as f32);
+        let mut sum = 0f64;
+        for i in 0..n {
+            let (sin_theta, cos_theta) = ((i as f32 + 0.5) * d_theta).sin_cos();
+            for j in 0..2 * n {
+                let (sin_phi, cos_phi) = ((j as f32 + 0.5) * d_phi).sin_cos();
+                let direction =
+                    Vec3 { x: sin_theta * cos_phi, y: cos_theta, z: sin_theta * sin_phi };
+                sum += f64::from(map.pdf(Vec3::ZERO, direction) * sin_theta * d_theta * d_phi);
+            }
+        }
+        assert!((sum - 1.).abs() < 1e-3, "{sum}");
+    }
+}
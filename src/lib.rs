@@ -1,9 +1,31 @@
+pub mod aabb;
+pub mod bvh;
 pub mod color;
+pub mod environment;
+pub mod light;
 pub mod material;
+pub mod medium;
+pub mod mesh;
+pub mod noise;
+pub mod obj;
+pub mod output;
 pub mod ray;
+pub mod render;
+pub mod sampling;
+pub mod scene;
 pub mod shape;
+pub mod sky;
+#[cfg(test)]
+mod testing;
+pub mod texture;
+pub mod tile;
+pub mod tonemap;
 pub mod tracer;
+pub mod transform;
 pub mod vec;
+pub mod volume;
+
+use fastrand::Rng;
 
 pub fn delimited_int<T: ToString>(delim: char, value: T) -> String {
     let as_str = value.to_string();
@@ -19,3 +41,17 @@ pub fn delimited_int<T: ToString>(delim: char, value: T) -> String {
     }
     delimited
 }
+
+// An independent random stream for every pixel, so that a render only depends
+// on the seed and not on how pixels are distributed across threads.
+pub fn pixel_rng(seed: u64, x: u32, y: u32) -> Rng {
+    let index = (u64::from(y) << 32) | u64::from(x);
+    Rng::with_seed(splitmix64(seed ^ splitmix64(index)))
+}
+
+pub(crate) fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,275 @@
+use std::{fmt::Debug, sync::Arc};
+
+use fastrand::Rng;
+
+use crate::{
+    ray::Ray,
+    sampling::{self, Onb},
+    shape::{HitRecord, Sphere, hit_sphere},
+    vec::Vec3,
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct LightSample {
+    pub direction: Vec3,
+    pub distance: f32,
+    pub radiance: Vec3,
+    pub pdf: f32,
+    // set for lights scattered rays can never find, like points, which are only
+    // reached by sampling them; `radiance` is then the light arriving from the
+    // light as a whole
+    pub delta: bool,
+}
+
+pub trait Light: Debug + Sync {
+    // Samples a unit direction from `origin` towards the light, the pdf is with
+    // respect to solid angle as seen from `origin`.
+    fn sample(&self, origin: Vec3, rng: &mut Rng) -> Option<LightSample>;
+
+    // Density of `sample` picking the unit `direction` from `origin`, assuming it
+    // points at the light.
+    fn pdf(&self, origin: Vec3, direction: Vec3) -> f32;
+
+    // Whether this light is what tracing `ray` through the scene found, given the
+    // hit or None if the ray escaped.
+    fn is_hit_by(&self, ray: &Ray, rec: Option<&HitRecord>) -> bool;
+}
+
+// Lights that are also the scene's background, like an environment map.
+impl<L: Light + Send + ?Sized> Light for Arc<L> {
+    fn sample(&self, origin: Vec3, rng: &mut Rng) -> Option<LightSample> {
+        (**self).sample(origin, rng)
+    }
+
+    fn pdf(&self, origin: Vec3, direction: Vec3) -> f32 { (**self).pdf(origin, direction) }
+
+    fn is_hit_by(&self, ray: &Ray, rec: Option<&HitRecord>) -> bool { (**self).is_hit_by(ray, rec) }
+}
+
+impl Light for Sphere {
+    fn sample(&self, origin: Vec3, rng: &mut Rng) -> Option<LightSample> {
+        // sample uniformly within the cone the sphere subtends
+        let cone_height = cone_height(self, origin)?;
+        let to_center = self.center - origin;
+
+        let direction = Onb::new(to_center.unit()).local(sampling::uniform_cone(rng, cone_height));
+
+        // the sphere doesn't move, any time will do
+        let ray = Ray { origin, direction, time: 0. };
+        let rec = hit_sphere(self.center, self.radius, &*self.material, &ray, 0., f32::MAX)?;
+
+        Some(LightSample {
+            direction,
+            distance: rec.distance,
+            radiance: self.material.emitted(&rec),
+            pdf: sampling::uniform_cone_pdf(cone_height),
+            delta: false,
+        })
+    }
+
+    fn pdf(&self, origin: Vec3, _direction: Vec3) -> f32 {
+        cone_height(self, origin).map_or(0., sampling::uniform_cone_pdf)
+    }
+
+    fn is_hit_by(&self, ray: &Ray, rec: Option<&HitRecord>) -> bool {
+        // the scene reports the distance computed by the sphere itself, so this is
+        // an exact comparison
+        rec.is_some_and(|rec| {
+            hit_sphere(self.center, self.radius, &*self.material, ray, 0.001, f32::MAX)
+                .is_some_and(|own| own.distance == rec.distance)
+        })
+    }
+}
+
+// 1 - cos of the angle the sphere subtends from `origin`, computed without the
+// cancellation for small or distant spheres. None from inside the sphere.
+fn cone_height(sphere: &Sphere, origin: Vec3) -> Option<f32> {
+    let dist_squared = (sphere.center - origin).squared_length();
+    let radius_squared = sphere.radius * sphere.radius;
+    if dist_squared <= radius_squared {
+        return None;
+    }
+    let sin_theta_max_squared = radius_squared / dist_squared;
+    let cos_theta_max = (1. - sin_theta_max_squared).sqrt();
+    Some(sin_theta_max_squared / (1. + cos_theta_max))
+}
+
+// A point emitting `intensity` equally in all directions.
+#[derive(Clone, Copy, Debug)]
+pub struct PointLight {
+    pub position: Vec3,
+    pub intensity: Vec3,
+}
+
+impl Light for PointLight {
+    fn sample(&self, origin: Vec3, _rng: &mut Rng) -> Option<LightSample> {
+        let to_light = self.position - origin;
+        let dist_squared = to_light.squared_length();
+        let distance = dist_squared.sqrt();
+        Some(LightSample {
+            direction: to_light / distance,
+            distance,
+            radiance: self.intensity / dist_squared,
+            pdf: 1.,
+            delta: true,
+        })
+    }
+
+    fn pdf(&self, _origin: Vec3, _direction: Vec3) -> f32 { 0. }
+
+    fn is_hit_by(&self, _ray: &Ray, _rec: Option<&HitRecord>) -> bool { false }
+}
+
+// A point light shining in a cone around `direction`, at full intensity within
+// the inner angle and fading out smoothly towards the outer one.
+#[derive(Clone, Copy, Debug)]
+pub struct SpotLight {
+    pub position: Vec3,
+    pub direction: Vec3,
+    pub intensity: Vec3,
+    pub cos_inner: f32,
+    pub cos_outer: f32,
+}
+
+impl SpotLight {
+    // Angles are in degrees from the cone's axis.
+    pub fn new(
+        position: Vec3,
+        direction: Vec3,
+        intensity: Vec3,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> SpotLight {
+        let outer_angle = outer_angle.clamp(0., 180.);
+        SpotLight {
+            position,
+            direction: direction.unit(),
+            intensity,
+            cos_inner: inner_angle.clamp(0., outer_angle).to_radians().cos(),
+            cos_outer: outer_angle.to_radians().cos(),
+        }
+    }
+
+    fn falloff(&self, cos_theta: f32) -> f32 {
+        if cos_theta >= self.cos_inner {
+            1.
+        } else if cos_theta <= self.cos_outer {
+            0.
+        } else {
+            let t = (cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer);
+            t * t * (3. - 2. * t)
+        }
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, origin: Vec3, _rng: &mut Rng) -> Option<LightSample> {
+        let to_light = self.position - origin;
+        let dist_squared = to_light.squared_length();
+        let distance = dist_squared.sqrt();
+        let direction = to_light / distance;
+        let falloff = self.falloff(-direction.dot(self.direction));
+        if falloff <= 0. {
+            return None;
+        }
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: self.intensity * (falloff / dist_squared),
+            pdf: 1.,
+            delta: true,
+        })
+    }
+
+    fn pdf(&self, _origin: Vec3, _direction: Vec3) -> f32 { 0. }
+
+    fn is_hit_by(&self, _ray: &Ray, _rec: Option<&HitRecord>) -> bool { false }
+}
+
+// A distant light like the sun, giving `irradiance` to surfaces facing it.
+// With an angular diameter it's a disk in the sky casting soft shadows,
+// otherwise all its light arrives from a single direction.
+#[derive(Clone, Copy, Debug)]
+pub struct DirectionalLight {
+    // unit vector towards the light
+    pub direction: Vec3,
+    pub irradiance: Vec3,
+    // 1 - cos of the disk's angular radius
+    pub cone_height: f32,
+}
+
+impl DirectionalLight {
+    // `direction` points towards the light, the angular diameter is in degrees.
+    pub fn new(direction: Vec3, irradiance: Vec3, angular_diameter: f32) -> DirectionalLight {
+        let half_angle = angular_diameter.clamp(0., 180.).to_radians() / 2.;
+        // 1 - cos(x) = 2 sin^2(x / 2), without the cancellation for small disks
+        let cone_height = 2. * (half_angle / 2.).sin().powi(2);
+        DirectionalLight { direction: direction.unit(), irradiance, cone_height }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _origin: Vec3, rng: &mut Rng) -> Option<LightSample> {
+        if self.cone_height <= 0. {
+            return Some(LightSample {
+                direction: self.direction,
+                distance: f32::MAX,
+                radiance: self.irradiance,
+                pdf: 1.,
+                delta: true,
+            });
+        }
+
+        let direction =
+            Onb::new(self.direction).local(sampling::uniform_cone(rng, self.cone_height));
+        // the disk isn't part of the scene, so scattered rays never find it
+        let pdf = sampling::uniform_cone_pdf(self.cone_height);
+        Some(LightSample {
+            direction,
+            distance: f32::MAX,
+            radiance: self.irradiance * pdf,
+            pdf,
+            delta: true,
+        })
+    }
+
+    fn pdf(&self, _origin: Vec3, _direction: Vec3) -> f32 { 0. }
+
+    fn is_hit_by(&self, _ray: &Ray, _rec: Option<&HitRecord>) -> bool { false }
+}
+
+#[derive(Debug, Default)]
+pub struct Lights<'a>(Vec<Box<dyn Light + 'a>>);
+
+impl<'a> Lights<'a> {
+    pub fn new() -> Lights<'a> {
+        let v = Vec::new();
+        Lights(v)
+    }
+
+    pub fn add<T: Light + 'a>(&mut self, light: T) { self.0.push(Box::new(light)); }
+
+    pub fn size(&self) -> usize { self.0.len() }
+
+    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+
+    // Samples one light picked uniformly, the selection probability is folded
+    // into the pdf.
+    pub fn sample(&self, origin: Vec3, rng: &mut Rng) -> Option<LightSample> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let light = &self.0[rng.usize(..self.0.len())];
+        light.sample(origin, rng).map(|s| LightSample { pdf: s.pdf / self.0.len() as f32, ..s })
+    }
+
+    // Density of `sample` picking the direction of `ray` from its origin, given
+    // the hit it found in the scene or None if it escaped; 0 if it didn't find a
+    // light.
+    pub fn pdf(&self, ray: &Ray, rec: Option<&HitRecord>) -> f32 {
+        match self.0.iter().find(|light| light.is_hit_by(ray, rec)) {
+            Some(light) => light.pdf(ray.origin, ray.direction.unit()) / self.0.len() as f32,
+            None => 0.,
+        }
+    }
+}
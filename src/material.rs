@@ -1,103 +1,335 @@
+use std::{f32::consts::PI, fmt::Debug};
+
 use fastrand::Rng;
 
-use crate::{ray::Ray, shape::HitRecord, vec::Vec3};
+use crate::{
+    ray::Ray,
+    sampling::{self, Onb},
+    shape::HitRecord,
+    texture::Texture,
+    vec::Vec3,
+};
 
 #[derive(Clone, Copy, Debug)]
-pub enum Material {
-    Dielectric(f32),
-    Lambertian(Vec3),
-    Metal(Vec3, f32),
+pub struct ScatterSample {
+    pub ray: Ray,
+    // BSDF times the cosine term for the sampled direction, or the phase
+    // function; for specular samples the weight of the ray
+    pub f: Vec3,
+    // solid angle density of the direction, None for specular samples which only
+    // have discrete directions to pick from
+    pub pdf: Option<f32>,
 }
 
-impl Material {
+impl ScatterSample {
+    // Throughput of the sampled ray.
     #[inline]
-    fn reflect(v: &Vec3, n: &Vec3) -> Vec3 { *v - *n * v.dot(*n) * 2. }
+    pub fn weight(&self) -> Vec3 {
+        match self.pdf {
+            Some(pdf) => self.f / pdf,
+            None => self.f,
+        }
+    }
+}
 
-    #[inline]
-    fn refract(v: &Vec3, n: &Vec3, ni_over_nt: f32) -> Option<Vec3> {
-        let uv = v.unit();
-        let dt = uv.dot(*n);
-        let discriminant = 1. - ni_over_nt * ni_over_nt * (1. - dt * dt);
-
-        if discriminant > 0. {
-            let refracted = (uv - *n * dt) * ni_over_nt - *n * discriminant.sqrt();
-            Some(refracted)
+// How light interacts with a surface, or with a medium through its phase
+// function. Shapes share materials through an `Arc`, so other crates can plug
+// in their own.
+pub trait Material: Debug + Send + Sync {
+    // Samples the direction the ray continues in, None if it's absorbed.
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, rng: &mut Rng) -> Option<ScatterSample>;
+
+    // BRDF times the cosine term for light arriving from the unit `direction`, or
+    // the phase function for media.
+    fn eval(&self, _ray_in: &Ray, _rec: &HitRecord, _direction: Vec3) -> Vec3 { Vec3::ZERO }
+
+    // Solid angle density of `scatter` picking the unit `direction`.
+    fn pdf(&self, _ray_in: &Ray, _rec: &HitRecord, _direction: Vec3) -> f32 { 0. }
+
+    fn emitted(&self, _rec: &HitRecord) -> Vec3 { Vec3::ZERO }
+
+    // Specular materials only scatter into discrete directions at this hit, so
+    // they can't be evaluated for arbitrary directions, e.g. ones towards a light.
+    fn is_specular(&self, _rec: &HitRecord) -> bool { false }
+
+    // Emissive spheres and triangles are sampled directly as lights.
+    fn is_emissive(&self) -> bool { false }
+}
+
+#[inline]
+fn reflect(v: &Vec3, n: &Vec3) -> Vec3 { *v - *n * v.dot(*n) * 2. }
+
+#[inline]
+fn refract(v: &Vec3, n: &Vec3, ni_over_nt: f32) -> Option<Vec3> {
+    let uv = v.unit();
+    let dt = uv.dot(*n);
+    let discriminant = 1. - ni_over_nt * ni_over_nt * (1. - dt * dt);
+
+    if discriminant > 0. {
+        let refracted = (uv - *n * dt) * ni_over_nt - *n * discriminant.sqrt();
+        Some(refracted)
+    } else {
+        None
+    }
+}
+
+// Whether `direction` leaves the surface on the side the ray arrived from,
+// which the shading normal can't tell when it's interpolated from the vertex
+// normals.
+#[inline]
+fn above_surface(rec: &HitRecord, direction: Vec3) -> bool {
+    direction.dot(rec.geometric_normal) > 0.
+}
+
+#[inline]
+fn schlick(cosine: f32, ref_idx: f32) -> f32 {
+    let r0 = (1. - ref_idx) / (1. + ref_idx);
+    r0 * r0 + (1. - r0 * r0) * (1. - cosine).powi(5)
+}
+
+#[inline]
+fn henyey_greenstein(cos_theta: f32, g: f32) -> f32 {
+    let denominator = 1. + g * g - 2. * g * cos_theta;
+    (1. - g * g) / (4. * PI * denominator * denominator.max(0.).sqrt())
+}
+
+// Glass-like refraction with the given index of refraction.
+#[derive(Clone, Copy, Debug)]
+pub struct Dielectric(pub f32);
+
+impl Material for Dielectric {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, rng: &mut Rng) -> Option<ScatterSample> {
+        let ref_idx = self.0;
+        let cosine = -ray_in.direction.dot(rec.normal) / ray_in.direction.length();
+        let (ni_over_nt, cosine) =
+            if rec.front_face { (1. / ref_idx, cosine) } else { (ref_idx, ref_idx * cosine) };
+
+        if let Some(refracted) = refract(&ray_in.direction, &rec.normal, ni_over_nt) {
+            let reflect_prob = schlick(cosine, ref_idx);
+
+            let scattered = if rng.f32() < reflect_prob {
+                let reflected = reflect(&ray_in.direction, &rec.normal);
+                Ray { origin: rec.point, direction: reflected, time: ray_in.time }
+            } else {
+                Ray { origin: rec.point, direction: refracted, time: ray_in.time }
+            };
+            Some(ScatterSample { ray: scattered, f: Vec3::ONE, pdf: None })
         } else {
-            None
+            let reflected = reflect(&ray_in.direction, &rec.normal);
+            let scattered = Ray { origin: rec.point, direction: reflected, time: ray_in.time };
+            Some(ScatterSample { ray: scattered, f: Vec3::ONE, pdf: None })
         }
     }
 
-    #[inline]
-    fn schlick(cosine: f32, ref_idx: f32) -> f32 {
-        let r0 = (1. - ref_idx) / (1. + ref_idx);
-        r0 * r0 + (1. - r0 * r0) * (1. - cosine).powi(5)
-    }
-
-    fn random_in_unit_sphere(rng: &mut Rng) -> Vec3 {
-        let mut p: Vec3;
-        while {
-            let rnd = Vec3 { x: rng.f32(), y: rng.f32(), z: rng.f32() };
-            p = rnd * 2. - Vec3::ONE;
-
-            p.squared_length() >= 1.
-        } {}
-
-        p
-    }
-
-    pub fn scatter(&self, ray_in: &Ray, rec: &HitRecord, rng: &mut Rng) -> Option<(Vec3, Ray)> {
-        use self::Material::*;
-
-        match *self {
-            Dielectric(ref_idx) => {
-                let (outward_normal, ni_over_nt, cosine) = if ray_in.direction.dot(rec.normal) > 0.
-                {
-                    let cosine =
-                        ref_idx * ray_in.direction.dot(rec.normal) / ray_in.direction.length();
-                    (-rec.normal, ref_idx, cosine)
-                } else {
-                    let cosine = -ray_in.direction.dot(rec.normal) / ray_in.direction.length();
-                    (rec.normal, 1. / ref_idx, cosine)
-                };
-
-                if let Some(refracted) =
-                    Material::refract(&ray_in.direction, &outward_normal, ni_over_nt)
-                {
-                    let reflect_prob = Material::schlick(cosine, ref_idx);
-
-                    let scattered = if rng.f32() < reflect_prob {
-                        let reflected = Material::reflect(&ray_in.direction, &rec.normal);
-                        Ray { origin: rec.point, direction: reflected }
-                    } else {
-                        Ray { origin: rec.point, direction: refracted }
-                    };
-                    Some((Vec3::ONE, scattered))
-                } else {
-                    let reflected = Material::reflect(&ray_in.direction, &rec.normal);
-                    let scattered = Ray { origin: rec.point, direction: reflected };
-                    Some((Vec3::ONE, scattered))
-                }
-            }
+    fn is_specular(&self, _rec: &HitRecord) -> bool { true }
+}
+
+// Emits the given radiance and absorbs everything arriving.
+#[derive(Clone, Debug)]
+pub struct DiffuseLight(pub Texture);
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _ray_in: &Ray, _rec: &HitRecord, _rng: &mut Rng) -> Option<ScatterSample> {
+        None
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Vec3 { self.0.value(rec.u, rec.v, rec.point) }
+
+    fn is_emissive(&self) -> bool { true }
+}
+
+// Scatters uniformly in all directions, the phase function of media.
+#[derive(Clone, Debug)]
+pub struct Isotropic(pub Texture);
+
+impl Material for Isotropic {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, rng: &mut Rng) -> Option<ScatterSample> {
+        let direction = sampling::uniform_sphere(rng);
+        Some(ScatterSample {
+            ray: Ray { origin: rec.point, direction, time: ray_in.time },
+            f: self.eval(ray_in, rec, direction),
+            pdf: Some(sampling::uniform_sphere_pdf()),
+        })
+    }
+
+    fn eval(&self, _ray_in: &Ray, rec: &HitRecord, _direction: Vec3) -> Vec3 {
+        self.0.value(rec.u, rec.v, rec.point) * sampling::uniform_sphere_pdf()
+    }
 
-            Lambertian(albedo) => {
-                let rnd = Self::random_in_unit_sphere(rng);
-                let target = rec.point + rec.normal + rnd;
-                let scattered = Ray { origin: rec.point, direction: target - rec.point };
-                Some((albedo, scattered))
+    fn pdf(&self, _ray_in: &Ray, _rec: &HitRecord, _direction: Vec3) -> f32 {
+        sampling::uniform_sphere_pdf()
+    }
+}
+
+// Albedo and asymmetry of a phase function favouring forward (positive) or
+// backward (negative) scattering.
+#[derive(Clone, Debug)]
+pub struct HenyeyGreenstein(pub Texture, pub f32);
+
+impl Material for HenyeyGreenstein {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, rng: &mut Rng) -> Option<ScatterSample> {
+        let g = self.1;
+        // invert the phase function's CDF for the angle to the incoming direction
+        let xi = rng.f32();
+        let cos_theta = if g.abs() < 1e-3 {
+            1. - 2. * xi
+        } else {
+            let s = (1. - g * g) / (1. - g + 2. * g * xi);
+            ((1. + g * g - s * s) / (2. * g)).clamp(-1., 1.)
+        };
+        let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+        let phi = 2. * PI * rng.f32();
+
+        let direction = Onb::new(ray_in.direction.unit()).local(Vec3 {
+            x: sin_theta * phi.cos(),
+            y: sin_theta * phi.sin(),
+            z: cos_theta,
+        });
+        let pdf = self.pdf(ray_in, rec, direction);
+        Some(ScatterSample {
+            ray: Ray { origin: rec.point, direction, time: ray_in.time },
+            f: self.0.value(rec.u, rec.v, rec.point) * pdf,
+            pdf: Some(pdf),
+        })
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
+        self.0.value(rec.u, rec.v, rec.point) * self.pdf(ray_in, rec, direction)
+    }
+
+    fn pdf(&self, ray_in: &Ray, _rec: &HitRecord, direction: Vec3) -> f32 {
+        henyey_greenstein(ray_in.direction.unit().dot(direction), self.1)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Lambertian(pub Texture);
+
+impl Material for Lambertian {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, rng: &mut Rng) -> Option<ScatterSample> {
+        let direction = Onb::new(rec.normal).local(sampling::cosine_hemisphere(rng));
+        let pdf = self.pdf(ray_in, rec, direction);
+        if pdf <= 0. {
+            return None;
+        }
+        Some(ScatterSample {
+            ray: Ray { origin: rec.point, direction, time: ray_in.time },
+            f: self.eval(ray_in, rec, direction),
+            pdf: Some(pdf),
+        })
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
+        self.0.value(rec.u, rec.v, rec.point) * self.pdf(ray_in, rec, direction)
+    }
+
+    fn pdf(&self, _ray_in: &Ray, rec: &HitRecord, direction: Vec3) -> f32 {
+        if !above_surface(rec, direction) {
+            return 0.;
+        }
+        sampling::cosine_hemisphere_pdf(rec.normal.dot(direction))
+    }
+}
+
+// Albedo and fuzz, the latter read from the first channel. Fuzzy reflections
+// offset the mirror direction by a random point in a ball of radius fuzz; a
+// fuzz of 0 is a perfect mirror.
+#[derive(Clone, Debug)]
+pub struct Metal(pub Texture, pub Texture);
+
+impl Metal {
+    const MIN_FUZZ: f32 = 1e-3;
+
+    fn fuzz(&self, rec: &HitRecord) -> f32 { self.1.value(rec.u, rec.v, rec.point).x.clamp(0., 1.) }
+}
+
+impl Material for Metal {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, rng: &mut Rng) -> Option<ScatterSample> {
+        let reflected = reflect(&ray_in.direction.unit(), &rec.normal);
+        let fuzz = self.fuzz(rec);
+        if fuzz < Self::MIN_FUZZ {
+            if !above_surface(rec, reflected) {
+                return None;
             }
+            return Some(ScatterSample {
+                ray: Ray { origin: rec.point, direction: reflected, time: ray_in.time },
+                f: self.0.value(rec.u, rec.v, rec.point),
+                pdf: None,
+            });
+        }
+
+        let direction = (reflected + sampling::uniform_ball(rng) * fuzz).unit();
+        // reflections below the surface are absorbed
+        let pdf = self.pdf(ray_in, rec, direction);
+        if pdf <= 0. {
+            return None;
+        }
+        Some(ScatterSample {
+            ray: Ray { origin: rec.point, direction, time: ray_in.time },
+            f: self.eval(ray_in, rec, direction),
+            pdf: Some(pdf),
+        })
+    }
 
-            Metal(albedo, fuzz) => {
-                let fuzz = fuzz.min(1.);
-                let reflected = Material::reflect(&ray_in.direction.unit(), &rec.normal);
-                let rnd = Self::random_in_unit_sphere(rng);
-                let scattered = Ray { origin: rec.point, direction: reflected + rnd * fuzz };
+    // Scaled so that every reflection above the surface is weighted by the albedo.
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
+        self.0.value(rec.u, rec.v, rec.point) * self.pdf(ray_in, rec, direction)
+    }
+
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: Vec3) -> f32 {
+        if !above_surface(rec, direction) {
+            return 0.;
+        }
+        let reflected = reflect(&ray_in.direction.unit(), &rec.normal);
+        sampling::offset_ball_pdf(reflected, direction, self.fuzz(rec))
+    }
+
+    fn is_specular(&self, rec: &HitRecord) -> bool { self.fuzz(rec) < Self::MIN_FUZZ }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{mesh::Mesh, shape::Shape};
+
+    // Scatters off the back of a quad facing +z, hit from below.
+    fn scatter_from_back(material: Arc<dyn Material>, rng: &mut Rng) -> Option<ScatterSample> {
+        let quad = Mesh::quad(
+            Vec3 { x: -1., y: -1., z: 0. },
+            Vec3 { x: 2., y: 0., z: 0. },
+            Vec3 { x: 0., y: 2., z: 0. },
+            material,
+        );
+        let ray = Ray {
+            origin: Vec3 { x: 0.1, y: 0.2, z: -1. },
+            direction: Vec3 { x: 0.3, y: -0.1, z: 1. },
+            time: 0.,
+        };
+        let rec = quad.hit(&ray, 0.001, f32::MAX).unwrap();
+        assert!(!rec.front_face);
+        rec.material.scatter(&ray, &rec, rng)
+    }
 
-                if scattered.direction.dot(rec.normal) > 0. {
-                    Some((albedo, scattered))
-                } else {
-                    None
+    #[test]
+    fn scatters_back_towards_the_ray() {
+        let mut rng = Rng::with_seed(3);
+        let materials: [Arc<dyn Material>; 3] = [
+            Arc::new(Lambertian(0.5.into())),
+            Arc::new(Metal(0.5.into(), 0.0.into())),
+            Arc::new(Metal(0.5.into(), 0.3.into())),
+        ];
+        for material in materials {
+            let mut scattered = 0;
+            for _ in 0..1000 {
+                if let Some(sample) = scatter_from_back(material.clone(), &mut rng) {
+                    assert!(sample.ray.direction.z < 0., "{material:?} {sample:?}");
+                    assert!(sample.weight().max_component() > 0.);
+                    scattered += 1;
                 }
             }
+            assert!(scattered > 900, "{material:?} scattered {scattered} times");
         }
     }
 }
@@ -0,0 +1,160 @@
+use std::sync::Arc;
+
+use fastrand::Rng;
+
+use crate::{
+    aabb::Aabb,
+    material::{HenyeyGreenstein, Isotropic, Material},
+    ray::Ray,
+    shape::{HitRecord, Shape},
+    splitmix64,
+    texture::Texture,
+    vec::Vec3,
+    volume::VoxelGrid,
+};
+
+// A participating medium of constant density filling a closed `boundary`, such
+// as smoke or fog. Rays entering it scatter after an exponentially distributed
+// distance, off the isotropic phase function.
+#[derive(Clone, Debug)]
+pub struct ConstantMedium<S> {
+    pub boundary: S,
+    pub density: f32,
+    pub phase: Arc<dyn Material>,
+}
+
+impl<S: Shape> ConstantMedium<S> {
+    pub fn new(boundary: S, density: f32, albedo: Texture) -> Self {
+        ConstantMedium { boundary, density, phase: Arc::new(Isotropic(albedo)) }
+    }
+}
+
+impl<S: Shape> Shape for ConstantMedium<S> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.sample_hit(ray, t_min, t_max, &mut ray_rng(ray))
+    }
+
+    fn sample_hit(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        rng: &mut Rng,
+    ) -> Option<HitRecord<'_>> {
+        // where the ray line enters and leaves the boundary, before clipping it to
+        // the queried interval
+        let enter = self.boundary.hit(ray, f32::NEG_INFINITY, f32::INFINITY)?.distance;
+        let exit = self.boundary.hit(ray, enter + 1e-4, f32::INFINITY)?.distance;
+        let (enter, exit) = (enter.max(t_min).max(0.), exit.min(t_max));
+        if enter >= exit {
+            return None;
+        }
+
+        let speed = ray.direction.length();
+        let distance_inside = (exit - enter) * speed;
+        let hit_distance = -(1. - rng.f32()).ln() / self.density;
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        Some(volume_record(ray, enter + hit_distance / speed, &*self.phase))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> { self.boundary.bounding_box() }
+}
+
+// A medium whose density varies according to a voxel grid stretched over
+// `bounds`, scaled by `density`. Collisions are found with delta tracking
+// against the grid's maximum density.
+#[derive(Clone, Debug)]
+pub struct HeterogeneousMedium {
+    pub grid: Arc<VoxelGrid>,
+    pub bounds: Aabb,
+    pub density: f32,
+    pub phase: Arc<dyn Material>,
+}
+
+impl HeterogeneousMedium {
+    // `anisotropy` is the Henyey-Greenstein asymmetry, from -1 (backwards) to 1
+    // (forwards).
+    pub fn new(
+        grid: Arc<VoxelGrid>,
+        bounds: Aabb,
+        density: f32,
+        albedo: Texture,
+        anisotropy: f32,
+    ) -> Self {
+        let phase = Arc::new(HenyeyGreenstein(albedo, anisotropy.clamp(-0.99, 0.99)));
+        HeterogeneousMedium { grid, bounds, density, phase }
+    }
+}
+
+impl Shape for HeterogeneousMedium {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.sample_hit(ray, t_min, t_max, &mut ray_rng(ray))
+    }
+
+    fn sample_hit(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        rng: &mut Rng,
+    ) -> Option<HitRecord<'_>> {
+        let (enter, exit) = self.bounds.hit(ray, t_min.max(0.), t_max)?;
+        let majorant = self.grid.max() * self.density;
+        if majorant <= 0. {
+            return None;
+        }
+
+        // sample tentative collisions in a homogeneous medium of the majorant
+        // density, accepting them with the ratio of the actual density
+        let speed = ray.direction.length();
+        let extent = self.bounds.diagonal();
+        let mut t = enter;
+        loop {
+            t -= (1. - rng.f32()).ln() / (majorant * speed);
+            if t >= exit {
+                return None;
+            }
+            let local = (ray.point_at(t) - self.bounds.min) / extent;
+            if rng.f32() * majorant < self.grid.density(local) * self.density {
+                return Some(volume_record(ray, t, &*self.phase));
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> { Some(self.bounds) }
+}
+
+fn volume_record<'a>(ray: &Ray, t: f32, phase: &'a dyn Material) -> HitRecord<'a> {
+    // the normal and surface parameters are meaningless inside a volume
+    let arbitrary = Vec3 { x: 1., y: 0., z: 0. };
+    HitRecord {
+        distance: t,
+        point: ray.point_at(t),
+        normal: arbitrary,
+        geometric_normal: arbitrary,
+        front_face: true,
+        u: 0.,
+        v: 0.,
+        dpdu: Vec3 { x: 0., y: 1., z: 0. },
+        dpdv: Vec3 { x: 0., y: 0., z: 1. },
+        material: phase,
+    }
+}
+
+// Queries without a random stream still get reproducible collisions, from a
+// stream derived from the ray itself. Paths sample media with their own stream.
+fn ray_rng(ray: &Ray) -> Rng {
+    let bits = [
+        ray.origin.x,
+        ray.origin.y,
+        ray.origin.z,
+        ray.direction.x,
+        ray.direction.y,
+        ray.direction.z,
+        ray.time,
+    ];
+    Rng::with_seed(bits.iter().fold(0, |h, x| splitmix64(h ^ u64::from(x.to_bits()))))
+}
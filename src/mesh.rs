@@ -0,0 +1,425 @@
+use std::sync::Arc;
+
+use fastrand::Rng;
+
+use crate::{
+    aabb::Aabb,
+    bvh::Bvh,
+    light::{Light, LightSample},
+    material::Material,
+    ray::Ray,
+    sampling::{self, Distribution1D},
+    shape::{HitRecord, Shape, Shapes},
+    transform::Transform,
+    vec::Vec3,
+};
+
+#[derive(Clone, Debug, Default)]
+pub struct MeshData {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<[f32; 2]>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Triangle {
+    pub mesh: Arc<MeshData>,
+    pub positions: [u32; 3],
+    pub normals: Option<[u32; 3]>,
+    // without texture coordinates the vertices get (0, 0), (1, 0) and (0, 1)
+    pub uvs: Option<[u32; 3]>,
+    pub material: Arc<dyn Material>,
+}
+
+impl Triangle {
+    #[inline]
+    fn vertices(&self) -> [Vec3; 3] {
+        let p = &self.mesh.positions;
+        [
+            p[self.positions[0] as usize],
+            p[self.positions[1] as usize],
+            p[self.positions[2] as usize],
+        ]
+    }
+
+    fn normal(&self) -> Vec3 {
+        let [p0, p1, p2] = self.vertices();
+        (p1 - p0).cross(p2 - p0).unit()
+    }
+
+    fn area(&self) -> f32 {
+        let [p0, p1, p2] = self.vertices();
+        (p1 - p0).cross(p2 - p0).length() / 2.
+    }
+
+    // Watertight ray/triangle intersection (Woop, Benthin, Wald 2013). Returns the
+    // distance along the ray and the barycentric weights of the three vertices.
+    fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, [f32; 3])> {
+        let d = ray.direction;
+        let kz = if d.x.abs() > d.y.abs() {
+            if d.x.abs() > d.z.abs() { 0 } else { 2 }
+        } else if d.y.abs() > d.z.abs() {
+            1
+        } else {
+            2
+        };
+        let mut kx = (kz + 1) % 3;
+        let mut ky = (kx + 1) % 3;
+        if d[kz] < 0. {
+            std::mem::swap(&mut kx, &mut ky);
+        }
+
+        let sz = 1. / d[kz];
+        let sx = d[kx] * sz;
+        let sy = d[ky] * sz;
+
+        let [a, b, c] = self.vertices().map(|v| v - ray.origin);
+        let (ax, ay) = (a[kx] - sx * a[kz], a[ky] - sy * a[kz]);
+        let (bx, by) = (b[kx] - sx * b[kz], b[ky] - sy * b[kz]);
+        let (cx, cy) = (c[kx] - sx * c[kz], c[ky] - sy * c[kz]);
+
+        let mut u = cx * by - cy * bx;
+        let mut v = ax * cy - ay * cx;
+        let mut w = bx * ay - by * ax;
+
+        // fall back to double precision on edges to stay watertight
+        if u == 0. || v == 0. || w == 0. {
+            u = (f64::from(cx) * f64::from(by) - f64::from(cy) * f64::from(bx)) as f32;
+            v = (f64::from(ax) * f64::from(cy) - f64::from(ay) * f64::from(cx)) as f32;
+            w = (f64::from(bx) * f64::from(ay) - f64::from(by) * f64::from(ax)) as f32;
+        }
+
+        if (u < 0. || v < 0. || w < 0.) && (u > 0. || v > 0. || w > 0.) {
+            return None;
+        }
+
+        let det = u + v + w;
+        if det == 0. {
+            return None;
+        }
+
+        let t = (u * a[kz] + v * b[kz] + w * c[kz]) * sz / det;
+        if t > t_min && t < t_max {
+            let inv_det = 1. / det;
+            Some((t, [u * inv_det, v * inv_det, w * inv_det]))
+        } else {
+            None
+        }
+    }
+}
+
+impl Shape for Triangle {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (t, [b0, b1, b2]) = self.intersect(ray, t_min, t_max)?;
+
+        let [p0, p1, p2] = self.vertices();
+        let geometric_normal = self.normal();
+        let normal = match self.normals {
+            Some(idx) => {
+                let n = &self.mesh.normals;
+                (n[idx[0] as usize] * b0 + n[idx[1] as usize] * b1 + n[idx[2] as usize] * b2).unit()
+            }
+            None => geometric_normal,
+        };
+
+        let [uv0, uv1, uv2] = match self.uvs {
+            Some(idx) => idx.map(|i| self.mesh.uvs[i as usize]),
+            None => [[0., 0.], [1., 0.], [0., 1.]],
+        };
+        let u = uv0[0] * b0 + uv1[0] * b1 + uv2[0] * b2;
+        let v = uv0[1] * b0 + uv1[1] * b1 + uv2[1] * b2;
+
+        // solve the edges for the derivatives, falling back to any tangent frame
+        // when the texture coordinates are degenerate
+        let (du02, dv02) = (uv0[0] - uv2[0], uv0[1] - uv2[1]);
+        let (du12, dv12) = (uv1[0] - uv2[0], uv1[1] - uv2[1]);
+        let (dp02, dp12) = (p0 - p2, p1 - p2);
+        let det = du02 * dv12 - dv02 * du12;
+        let (dpdu, dpdv) = if det.abs() > 1e-8 {
+            let inv_det = 1. / det;
+            ((dp02 * dv12 - dp12 * dv02) * inv_det, (dp12 * du02 - dp02 * du12) * inv_det)
+        } else {
+            geometric_normal.orthonormal_basis()
+        };
+
+        let rec = HitRecord {
+            distance: t,
+            point: ray.point_at(t),
+            normal,
+            geometric_normal,
+            front_face: true,
+            u,
+            v,
+            dpdu,
+            dpdv,
+            material: &*self.material,
+        };
+        // open meshes and inconsistent winding shade the same from either side
+        Some(rec.facing(ray))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> { Some(Aabb::from_points(self.vertices())) }
+}
+
+#[derive(Debug)]
+pub struct Mesh {
+    bvh: Bvh<'static>,
+    // kept to be sampled as lights, see `MeshLight`
+    emissive: Vec<Triangle>,
+}
+
+impl Mesh {
+    pub fn new<I: IntoIterator<Item = Triangle>>(triangles: I) -> Mesh {
+        let mut shapes = Shapes::new();
+        let mut emissive = Vec::new();
+        for triangle in triangles {
+            if triangle.material.is_emissive() {
+                emissive.push(triangle.clone());
+            }
+            shapes.add(triangle);
+        }
+        Mesh { bvh: Bvh::new(shapes), emissive }
+    }
+
+    // A parallelogram spanned by `u` and `v`, facing towards `u` cross `v`.
+    pub fn quad(origin: Vec3, u: Vec3, v: Vec3, material: Arc<dyn Material>) -> Mesh {
+        Mesh::parallelograms(&[(origin, u, v)], material)
+    }
+
+    // An axis-aligned box with its faces pointing outwards.
+    pub fn cuboid(min: Vec3, max: Vec3, material: Arc<dyn Material>) -> Mesh {
+        let d = max - min;
+        let (x, y, z) =
+            (Vec3 { y: 0., z: 0., ..d }, Vec3 { x: 0., z: 0., ..d }, Vec3 { x: 0., y: 0., ..d });
+        Mesh::parallelograms(
+            &[
+                (min, z, y),
+                (min + x, y, z),
+                (min, x, z),
+                (min + y, z, x),
+                (min, y, x),
+                (min + z, x, y),
+            ],
+            material,
+        )
+    }
+
+    fn parallelograms(faces: &[(Vec3, Vec3, Vec3)], material: Arc<dyn Material>) -> Mesh {
+        let mut data = MeshData::default();
+        for &(origin, u, v) in faces {
+            data.positions.extend([origin, origin + u, origin + u + v, origin + v]);
+            data.uvs.extend([[0., 0.], [1., 0.], [1., 1.], [0., 1.]]);
+        }
+
+        let mesh = Arc::new(data);
+        Mesh::new((0..faces.len() as u32).flat_map(|face| {
+            [[0, 1, 2], [0, 2, 3]].map(|corners| {
+                let positions = corners.map(|c| 4 * face + c);
+                Triangle {
+                    mesh: Arc::clone(&mesh),
+                    positions,
+                    normals: None,
+                    uvs: Some(positions),
+                    material: Arc::clone(&material),
+                }
+            })
+        }))
+    }
+
+    pub fn size(&self) -> usize { self.bvh.size() }
+}
+
+impl Shape for Mesh {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.bvh.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> { self.bvh.bounding_box() }
+}
+
+// The emissive triangles of a mesh placed in the scene, sampled uniformly by
+// area. They're copied into world space, as a transform scaling the mesh would
+// also change the density of the sampled points.
+#[derive(Debug)]
+pub struct MeshLight {
+    triangles: Vec<Triangle>,
+    distribution: Distribution1D,
+    area: f32,
+}
+
+impl MeshLight {
+    // None if the mesh doesn't emit any light.
+    pub fn new(mesh: &Mesh, transform: &Transform) -> Option<MeshLight> {
+        let mut data = MeshData::default();
+        let mut uvs = Vec::with_capacity(mesh.emissive.len());
+        for triangle in &mesh.emissive {
+            data.positions.extend(triangle.vertices().map(|p| transform.point(p)));
+            uvs.push(triangle.uvs.map(|idx| {
+                let first = data.uvs.len() as u32;
+                data.uvs.extend(idx.map(|i| triangle.mesh.uvs[i as usize]));
+                [first, first + 1, first + 2]
+            }));
+        }
+
+        let data = Arc::new(data);
+        let triangles: Vec<_> = mesh
+            .emissive
+            .iter()
+            .zip(uvs)
+            .enumerate()
+            .map(|(i, (triangle, uvs))| {
+                let first = 3 * i as u32;
+                Triangle {
+                    mesh: Arc::clone(&data),
+                    positions: [first, first + 1, first + 2],
+                    normals: None,
+                    uvs,
+                    material: Arc::clone(&triangle.material),
+                }
+            })
+            .collect();
+
+        let areas: Vec<f32> = triangles.iter().map(Triangle::area).collect();
+        let area = areas.iter().sum();
+        if area <= 0. {
+            return None;
+        }
+        Some(MeshLight { triangles, distribution: Distribution1D::new(areas), area })
+    }
+
+    // The first of the light's triangles along the ray, with the distance to it.
+    fn closest(&self, ray: &Ray, t_min: f32) -> Option<(f32, &Triangle)> {
+        self.triangles.iter().fold(None, |acc, triangle| {
+            let t_max = acc.map_or(f32::MAX, |(t, _)| t);
+            triangle.intersect(ray, t_min, t_max).map(|(t, _)| (t, triangle)).or(acc)
+        })
+    }
+
+    // Converts the density of points on the light to solid angle from `origin`.
+    fn solid_angle_pdf(&self, distance: f32, normal: Vec3, direction: Vec3) -> f32 {
+        let cos_theta = normal.dot(direction).abs();
+        if cos_theta <= 0. { 0. } else { distance * distance / (cos_theta * self.area) }
+    }
+}
+
+impl Light for MeshLight {
+    fn sample(&self, origin: Vec3, rng: &mut Rng) -> Option<LightSample> {
+        let (_, _, i) = self.distribution.sample(rng.f32());
+        let triangle = &self.triangles[i];
+        let [p0, p1, p2] = triangle.vertices();
+        let [b0, b1, b2] = sampling::uniform_triangle(rng);
+        let direction = (p0 * b0 + p1 * b1 + p2 * b2 - origin).unit();
+
+        // intersect the triangle for the distance and the texture coordinates of
+        // the emission, as a ray finding the light would
+        let ray = Ray { origin, direction, time: 0. };
+        let rec = triangle.hit(&ray, 0., f32::MAX)?;
+        let pdf = self.solid_angle_pdf(rec.distance, rec.geometric_normal, direction);
+        if pdf <= 0. {
+            return None;
+        }
+        Some(LightSample {
+            direction,
+            distance: rec.distance,
+            radiance: rec.material.emitted(&rec),
+            pdf,
+            delta: false,
+        })
+    }
+
+    fn pdf(&self, origin: Vec3, direction: Vec3) -> f32 {
+        let ray = Ray { origin, direction, time: 0. };
+        match self.closest(&ray, 0.) {
+            Some((t, triangle)) => self.solid_angle_pdf(t, triangle.normal(), direction),
+            None => 0.,
+        }
+    }
+
+    fn is_hit_by(&self, ray: &Ray, rec: Option<&HitRecord>) -> bool {
+        // a transformed mesh is intersected in its own space in the scene, so the
+        // distances only agree up to rounding
+        rec.is_some_and(|rec| {
+            rec.material.is_emissive()
+                && self
+                    .closest(ray, 0.001)
+                    .is_some_and(|(t, _)| (t - rec.distance).abs() <= rec.distance * 1e-4)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::{DiffuseLight, Lambertian},
+        transform::Transformed,
+    };
+
+    fn triangle() -> Triangle {
+        let mesh = MeshData {
+            positions: vec![Vec3 { x: 0., y: 0., z: 0. }, Vec3 { x: 1., y: 0., z: 0. }, Vec3 {
+                x: 0.,
+                y: 1.,
+                z: 0.,
+            }],
+            ..MeshData::default()
+        };
+        Triangle {
+            mesh: Arc::new(mesh),
+            positions: [0, 1, 2],
+            normals: None,
+            uvs: None,
+            material: Arc::new(Lambertian(0.5.into())),
+        }
+    }
+
+    #[test]
+    fn normals_face_the_ray() {
+        let triangle = triangle();
+        // the winding order makes +z the front
+        for (z, front_face) in [(1., true), (-1., false)] {
+            let origin = Vec3 { x: 0.25, y: 0.25, z };
+            let ray = Ray { origin, direction: Vec3 { x: 0., y: 0., z: -z }, time: 0. };
+            let rec = triangle.hit(&ray, 0.001, f32::MAX).unwrap();
+            assert_eq!(rec.front_face, front_face);
+            let to_origin = origin - rec.point;
+            assert!(rec.normal.dot(to_origin) > 0.);
+            assert!(rec.geometric_normal.dot(to_origin) > 0.);
+        }
+    }
+
+    #[test]
+    fn light_pdf_matches_its_samples() {
+        let quad = Arc::new(Mesh::quad(
+            Vec3::ZERO,
+            Vec3 { x: 1., y: 0., z: 0. },
+            Vec3 { x: 0., y: 0., z: 1. },
+            Arc::new(DiffuseLight(Vec3::ONE.into())),
+        ));
+        let transform = Transform::translate(Vec3 { x: -1., y: 2., z: 0.5 })
+            * Transform::rotate(Vec3 { x: 1., y: 0., z: 1. }, 40.)
+            * Transform::scale(Vec3 { x: 3., y: 1., z: 0.5 });
+        let light = MeshLight::new(&quad, &transform).unwrap();
+        let shape = Transformed::new(Arc::clone(&quad), transform);
+
+        let mut rng = Rng::with_seed(4);
+        let origin = Vec3 { x: 0.2, y: 0.1, z: -0.3 };
+        for _ in 0..1000 {
+            let sample = light.sample(origin, &mut rng).unwrap();
+            let pdf = light.pdf(origin, sample.direction);
+            assert!((pdf / sample.pdf - 1.).abs() < 1e-3, "{pdf} vs {}", sample.pdf);
+
+            // the light recognizes itself in the scene, where it's transformed
+            let ray = Ray { origin, direction: sample.direction, time: 0. };
+            let rec = shape.hit(&ray, 0.001, f32::MAX).unwrap();
+            assert!(light.is_hit_by(&ray, Some(&rec)));
+        }
+
+        // the pdf integrates to 1 over all directions
+        let n = 200_000;
+        let total: f32 =
+            (0..n).map(|_| light.pdf(origin, sampling::uniform_sphere(&mut rng))).sum::<f32>()
+                / (n as f32 * sampling::uniform_sphere_pdf());
+        assert!((total - 1.).abs() < 0.03, "{total}");
+    }
+}
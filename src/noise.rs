@@ -0,0 +1,110 @@
+use std::fmt;
+
+use fastrand::Rng;
+
+use crate::vec::Vec3;
+
+const SIZE: usize = 256;
+
+// Lattice noise built on a shuffled permutation table: Perlin's gradient noise
+// and Worley's cellular noise, both periodic over 256 units.
+#[derive(Clone)]
+pub struct Noise {
+    perm: [u8; SIZE],
+    gradients: [Vec3; SIZE],
+    // one feature point per cell, relative to the cell's corner
+    points: [Vec3; SIZE],
+}
+
+impl fmt::Debug for Noise {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Noise").finish_non_exhaustive()
+    }
+}
+
+impl Noise {
+    pub fn new(rng: &mut Rng) -> Noise {
+        let mut perm = [0; SIZE];
+        for (i, p) in perm.iter_mut().enumerate() {
+            *p = i as u8;
+        }
+        rng.shuffle(&mut perm);
+
+        let gradients = [(); SIZE].map(|_| {
+            loop {
+                // rejection sampling keeps the directions uniform
+                let v = Vec3 { x: rng.f32(), y: rng.f32(), z: rng.f32() } * 2. - Vec3::ONE;
+                let length = v.length();
+                if length > 0.01 && length <= 1. {
+                    break v / length;
+                }
+            }
+        });
+        let points = [(); SIZE].map(|_| Vec3 { x: rng.f32(), y: rng.f32(), z: rng.f32() });
+
+        Noise { perm, gradients, points }
+    }
+
+    #[inline]
+    fn hash(&self, x: i64, y: i64, z: i64) -> usize {
+        let p = |i: i64| self.perm[(i & (SIZE as i64 - 1)) as usize] as i64;
+        p(p(p(x) + y) + z) as usize
+    }
+
+    // Gradient noise, roughly in [-1, 1] and 0 on the integer lattice.
+    pub fn perlin(&self, p: Vec3) -> f32 {
+        let cell = p.map(f32::floor);
+        let f = p - cell;
+        let (x, y, z) = (cell.x as i64, cell.y as i64, cell.z as i64);
+        // quintic fade curve, continuous up to the second derivative
+        let fade = |t: f32| t * t * t * (t * (t * 6. - 15.) + 10.);
+        let (u, v, w) = (fade(f.x), fade(f.y), fade(f.z));
+
+        let mut sum = 0.;
+        for (i, j, k) in (0..8).map(|c| (c & 1, (c >> 1) & 1, c >> 2)) {
+            let gradient = self.gradients[self.hash(x + i, y + j, z + k)];
+            let offset = f - Vec3 { x: i as f32, y: j as f32, z: k as f32 };
+            let weight = (if i == 0 { 1. - u } else { u })
+                * (if j == 0 { 1. - v } else { v })
+                * (if k == 0 { 1. - w } else { w });
+            sum += weight * gradient.dot(offset);
+        }
+        sum
+    }
+
+    // Sum of `octaves` layers of absolute noise, each at twice the frequency and
+    // half the weight of the previous one.
+    pub fn turbulence(&self, p: Vec3, octaves: u32) -> f32 {
+        let mut sum = 0.;
+        let mut p = p;
+        let mut weight = 1.;
+        for _ in 0..octaves {
+            sum += weight * self.perlin(p).abs();
+            p = p * 2.;
+            weight *= 0.5;
+        }
+        sum
+    }
+
+    // Distances to the closest and second closest feature point, with one point
+    // scattered in every unit cell.
+    pub fn voronoi(&self, p: Vec3) -> (f32, f32) {
+        let cell = p.map(f32::floor);
+        let (x, y, z) = (cell.x as i64, cell.y as i64, cell.z as i64);
+
+        let (mut f1, mut f2) = (f32::MAX, f32::MAX);
+        for c in 0..27 {
+            let (i, j, k) = (c % 3 - 1, (c / 3) % 3 - 1, c / 9 - 1);
+            let corner = cell + Vec3 { x: i as f32, y: j as f32, z: k as f32 };
+            let point = corner + self.points[self.hash(x + i, y + j, z + k)];
+            let d = (point - p).squared_length();
+            if d < f1 {
+                f2 = f1;
+                f1 = d;
+            } else if d < f2 {
+                f2 = d;
+            }
+        }
+        (f1.sqrt(), f2.sqrt())
+    }
+}
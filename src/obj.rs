@@ -0,0 +1,378 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    str::SplitWhitespace,
+    sync::Arc,
+};
+
+use crate::{
+    material::{Dielectric, DiffuseLight, Lambertian, Material, Metal},
+    mesh::{Mesh, MeshData, Triangle},
+    vec::Vec3,
+};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(PathBuf, io::Error),
+    Parse { path: PathBuf, line: usize, message: String },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            ObjError::Parse { path, line, message } => {
+                write!(f, "{}:{}: {}", path.display(), line, message)
+            }
+        }
+    }
+}
+
+impl Error for ObjError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ObjError::Io(_, err) => Some(err),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+struct Parser<'p> {
+    path: &'p Path,
+    line: usize,
+}
+
+impl<'p> Parser<'p> {
+    fn error<T, S: Into<String>>(&self, message: S) -> Result<T, ObjError> {
+        Err(ObjError::Parse {
+            path: self.path.to_owned(),
+            line: self.line,
+            message: message.into(),
+        })
+    }
+
+    fn f32(&self, args: &mut SplitWhitespace) -> Result<f32, ObjError> {
+        match args.next().map(str::parse) {
+            Some(Ok(x)) => Ok(x),
+            Some(Err(_)) => self.error("invalid number"),
+            None => self.error("missing number"),
+        }
+    }
+
+    fn vec3(&self, args: &mut SplitWhitespace) -> Result<Vec3, ObjError> {
+        Ok(Vec3 { x: self.f32(args)?, y: self.f32(args)?, z: self.f32(args)? })
+    }
+
+    // OBJ indices are 1-based, negative ones count back from the latest element
+    fn index(&self, s: &str, len: usize) -> Result<u32, ObjError> {
+        let i: i64 = match s.parse() {
+            Ok(i) => i,
+            Err(_) => return self.error(format!("invalid index '{s}'")),
+        };
+        let resolved = if i < 0 { len as i64 + i } else { i - 1 };
+        if resolved < 0 || resolved >= len as i64 {
+            return self.error(format!("index {i} out of range"));
+        }
+        Ok(resolved as u32)
+    }
+}
+
+// A triangle waiting for the mesh data to be complete.
+struct Face {
+    positions: [u32; 3],
+    normals: Option<[u32; 3]>,
+    uvs: Option<[u32; 3]>,
+    material: Arc<dyn Material>,
+}
+
+pub fn load<P: AsRef<Path>>(
+    path: P,
+    default_material: Arc<dyn Material>,
+) -> Result<Mesh, ObjError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|e| ObjError::Io(path.to_owned(), e))?;
+    parse(&source, path, default_material)
+}
+
+// Parses an OBJ file read from `path`, which names it in errors and locates
+// its material libraries.
+pub fn parse(
+    source: &str,
+    path: &Path,
+    default_material: Arc<dyn Material>,
+) -> Result<Mesh, ObjError> {
+    let mut materials = HashMap::new();
+    let mut material = default_material;
+
+    let mut data = MeshData::default();
+    let mut faces = Vec::new();
+
+    let mut parser = Parser { path, line: 0 };
+    for (n, line) in source.lines().enumerate() {
+        parser.line = n + 1;
+        let line = line.split('#').next().unwrap_or_default();
+        let mut args = line.split_whitespace();
+
+        match args.next() {
+            Some("v") => data.positions.push(parser.vec3(&mut args)?),
+            Some("vn") => data.normals.push(parser.vec3(&mut args)?),
+            Some("vt") => {
+                let u = parser.f32(&mut args)?;
+                // v is optional, and so is a third coordinate we don't use
+                let v = if args.clone().next().is_some() { parser.f32(&mut args)? } else { 0. };
+                data.uvs.push([u, v]);
+            }
+            Some("f") => {
+                let mut vertices = Vec::new();
+                for vertex in args {
+                    let mut parts = vertex.split('/');
+                    let position = parser.index(parts.next().unwrap(), data.positions.len())?;
+                    let uv = match parts.next() {
+                        Some(s) if !s.is_empty() => Some(parser.index(s, data.uvs.len())?),
+                        _ => None,
+                    };
+                    let normal = match parts.next() {
+                        Some(s) if !s.is_empty() => Some(parser.index(s, data.normals.len())?),
+                        _ => None,
+                    };
+                    vertices.push((position, uv, normal));
+                }
+                if vertices.len() < 3 {
+                    return parser.error("face with fewer than 3 vertices");
+                }
+
+                // triangulate polygons as a fan around the first vertex
+                for i in 1..vertices.len() - 1 {
+                    let [a, b, c] = [vertices[0], vertices[i], vertices[i + 1]];
+                    let uvs = match (a.1, b.1, c.1) {
+                        (Some(ta), Some(tb), Some(tc)) => Some([ta, tb, tc]),
+                        _ => None,
+                    };
+                    let normals = match (a.2, b.2, c.2) {
+                        (Some(na), Some(nb), Some(nc)) => Some([na, nb, nc]),
+                        _ => None,
+                    };
+                    faces.push(Face {
+                        positions: [a.0, b.0, c.0],
+                        normals,
+                        uvs,
+                        material: Arc::clone(&material),
+                    });
+                }
+            }
+            Some("mtllib") => {
+                let file = args.collect::<Vec<_>>().join(" ");
+                let mtl_path = path.parent().unwrap_or(Path::new("")).join(file);
+                materials.extend(load_mtl(&mtl_path)?);
+            }
+            Some("usemtl") => {
+                let name = args.collect::<Vec<_>>().join(" ");
+                material = match materials.get(&name) {
+                    Some(m) => Arc::clone(m),
+                    None => return parser.error(format!("unknown material '{name}'")),
+                };
+            }
+            // groups, objects and smoothing groups don't affect rendering
+            Some("vp" | "g" | "o" | "s" | "l" | "p") | None => {}
+            Some(keyword) => return parser.error(format!("unsupported statement '{keyword}'")),
+        }
+    }
+
+    let data = Arc::new(data);
+    let triangles = faces.into_iter().map(|f| Triangle {
+        mesh: Arc::clone(&data),
+        positions: f.positions,
+        normals: f.normals,
+        uvs: f.uvs,
+        material: f.material,
+    });
+
+    Ok(Mesh::new(triangles))
+}
+
+#[derive(Clone, Copy)]
+struct MtlMaterial {
+    diffuse: Vec3,
+    specular: Vec3,
+    emission: Vec3,
+    shininess: f32,
+    ior: f32,
+    dissolve: f32,
+    illum: u32,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        MtlMaterial {
+            diffuse: Vec3 { x: 0.8, y: 0.8, z: 0.8 },
+            specular: Vec3::ZERO,
+            emission: Vec3::ZERO,
+            shininess: 0.,
+            ior: 1.,
+            dissolve: 1.,
+            illum: 2,
+        }
+    }
+}
+
+impl From<MtlMaterial> for Arc<dyn Material> {
+    fn from(m: MtlMaterial) -> Self {
+        let has_specular = m.specular.x > 0. || m.specular.y > 0. || m.specular.z > 0.;
+        let has_emission = m.emission.x > 0. || m.emission.y > 0. || m.emission.z > 0.;
+        match m.illum {
+            _ if has_emission => Arc::new(DiffuseLight(m.emission.into())),
+            // refraction and glass illumination models, or anything see-through
+            4 | 6 | 7 | 9 => Arc::new(Dielectric(m.ior.max(1.))),
+            _ if m.dissolve < 1. => Arc::new(Dielectric(m.ior.max(1.))),
+            // reflection illumination models
+            3 | 5 | 8 if has_specular => {
+                // a Phong lobe of exponent Ns spreads reflections by a mean squared
+                // angle of about 2 / Ns, the fuzz ball by 2 fuzz^2 / 5; an exponent of
+                // 0 gives the roughest metal
+                let fuzz = (5. / (m.shininess + 5.)).sqrt();
+                Arc::new(Metal(m.specular.into(), fuzz.into()))
+            }
+            _ => Arc::new(Lambertian(m.diffuse.into())),
+        }
+    }
+}
+
+fn load_mtl(path: &Path) -> Result<HashMap<String, Arc<dyn Material>>, ObjError> {
+    let source = fs::read_to_string(path).map_err(|e| ObjError::Io(path.to_owned(), e))?;
+
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+
+    let mut parser = Parser { path, line: 0 };
+    for (n, line) in source.lines().enumerate() {
+        parser.line = n + 1;
+        let line = line.split('#').next().unwrap_or_default();
+        let mut args = line.split_whitespace();
+
+        let keyword = match args.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+
+        if keyword == "newmtl" {
+            if let Some((name, m)) = current.take() {
+                materials.insert(name, m.into());
+            }
+            current = Some((args.collect::<Vec<_>>().join(" "), MtlMaterial::default()));
+            continue;
+        }
+
+        let m = match current.as_mut() {
+            Some((_, m)) => m,
+            None => return parser.error(format!("'{keyword}' before any 'newmtl'")),
+        };
+        match keyword {
+            "Kd" => m.diffuse = parser.vec3(&mut args)?,
+            "Ks" => m.specular = parser.vec3(&mut args)?,
+            "Ke" => m.emission = parser.vec3(&mut args)?,
+            "Ns" => m.shininess = parser.f32(&mut args)?,
+            "Ni" => m.ior = parser.f32(&mut args)?,
+            "d" => m.dissolve = parser.f32(&mut args)?,
+            "Tr" => m.dissolve = 1. - parser.f32(&mut args)?,
+            "illum" => {
+                m.illum = match args.next().map(str::parse) {
+                    Some(Ok(illum)) => illum,
+                    _ => return parser.error("invalid illumination model"),
+                }
+            }
+            // ambient color, texture maps and the rest have no equivalent here
+            _ => {}
+        }
+    }
+
+    if let Some((name, m)) = current {
+        materials.insert(name, m.into());
+    }
+
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    const SQUARE: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+    fn default_material() -> Arc<dyn Material> { Arc::new(Lambertian(0.5.into())) }
+
+    // Parses the OBJ source next to a material library written to a scratch
+    // directory.
+    fn parse_with_mtl(name: &str, obj: &str, mtl: (&str, &str)) -> Result<Mesh, ObjError> {
+        let dir = TempDir::new(name);
+        dir.write(mtl.0, mtl.1);
+        parse(obj, &dir.path().join("square.obj"), default_material())
+    }
+
+    fn parse_error(result: Result<Mesh, ObjError>) -> (String, usize, String) {
+        match result {
+            Err(ObjError::Parse { path, line, message }) => {
+                (path.file_name().unwrap().to_string_lossy().into_owned(), line, message)
+            }
+            other => panic!("expected a parse error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn triangulates_faces_with_materials() {
+        let obj = format!(
+            "mtllib square.mtl\n{SQUARE}vt 0 0\nvn 0 0 1\nusemtl red\nf 1/1/1 2/1/1 3/1/1 4/1/1\n"
+        );
+        // unsupported MTL statements are skipped
+        let mtl = "newmtl red\nKa 0.1 0.1 0.1\nKd 0.8 0.1 0.1\nmap_Kd red.png\n";
+        let mesh = parse_with_mtl("valid", &obj, ("square.mtl", mtl)).unwrap();
+        assert_eq!(mesh.size(), 2);
+    }
+
+    #[test]
+    fn joins_names_with_spaces() {
+        let obj = format!("mtllib my square.mtl\n{SQUARE}usemtl dark  red\nf 1 2 3\n");
+        let mtl = "newmtl dark red\nKd 0.4 0.1 0.1\n";
+        let mesh = parse_with_mtl("names", &obj, ("my square.mtl", mtl)).unwrap();
+        assert_eq!(mesh.size(), 1);
+    }
+
+    #[test]
+    fn rejects_unknown_materials() {
+        let obj = format!("mtllib square.mtl\n{SQUARE}usemtl blue\nf 1 2 3\n");
+        let mtl = "newmtl red\nKd 0.8 0.1 0.1\n";
+        let (file, line, message) =
+            parse_error(parse_with_mtl("usemtl", &obj, ("square.mtl", mtl)));
+        assert_eq!((file.as_str(), line), ("square.obj", 6));
+        assert_eq!(message, "unknown material 'blue'");
+    }
+
+    #[test]
+    fn rejects_unsupported_statements() {
+        let obj = format!("{SQUARE}curv 0 1 1 2\n");
+        let (_, line, message) =
+            parse_error(parse(&obj, Path::new("curve.obj"), default_material()));
+        assert_eq!(line, 5);
+        assert_eq!(message, "unsupported statement 'curv'");
+    }
+
+    #[test]
+    fn reports_errors_in_material_libraries() {
+        let obj = format!("mtllib square.mtl\n{SQUARE}");
+        let mtl = "# no material yet\nKd 0.8 0.1 0.1\n";
+        let (file, line, message) = parse_error(parse_with_mtl("mtl", &obj, ("square.mtl", mtl)));
+        assert_eq!((file.as_str(), line), ("square.mtl", 2));
+        assert_eq!(message, "'Kd' before any 'newmtl'");
+    }
+
+    #[test]
+    fn rejects_out_of_range_indices() {
+        let obj = format!("{SQUARE}f 1 2 5\n");
+        let (_, line, message) =
+            parse_error(parse(&obj, Path::new("square.obj"), default_material()));
+        assert_eq!(line, 5);
+        assert_eq!(message, "index 5 out of range");
+    }
+}
@@ -0,0 +1,133 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use image::{ImageBuffer, ImageResult, Rgb, Rgb32FImage, RgbImage};
+
+use crate::{color::Color, render::Framebuffer, tonemap::ToneMapping, vec::Vec3};
+
+// Saves the framebuffer in the format implied by the file extension. OpenEXR,
+// Radiance HDR and PFM files keep the full floating-point range; anything else
+// is tone mapped and written display-referred as 8-bit sRGB.
+pub fn save<P: AsRef<Path>>(
+    path: P,
+    framebuffer: &Framebuffer,
+    tone_mapping: &ToneMapping,
+) -> ImageResult<()> {
+    let path = path.as_ref();
+    let (width, height) = (framebuffer.width(), framebuffer.height());
+    let pixels = framebuffer.pixels();
+    let extension =
+        path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).unwrap_or_default();
+
+    match extension.as_str() {
+        "exr" | "hdr" => {
+            let buf: Rgb32FImage = ImageBuffer::from_fn(width, height, |x, y| {
+                let c = pixels[(y * width + x) as usize];
+                Rgb([c.x, c.y, c.z])
+            });
+            buf.save(path)
+        }
+        "pfm" => save_pfm(path, width, height, pixels),
+        _ => {
+            let buf: RgbImage = ImageBuffer::from_fn(width, height, |x, y| {
+                let c = tone_mapping.apply(pixels[(y * width + x) as usize]);
+                Rgb(Color::from_linear(c).as_array())
+            });
+            buf.save(path)
+        }
+    }
+}
+
+// Portable float map: a short text header followed by little-endian RGB floats
+// in rows from bottom to top.
+fn save_pfm(path: &Path, width: u32, height: u32, pixels: &[Vec3]) -> ImageResult<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    write!(w, "PF\n{width} {height}\n-1.0\n")?;
+    for row in pixels.chunks(width as usize).rev() {
+        for c in row {
+            for v in [c.x, c.y, c.z] {
+                w.write_all(&v.to_le_bytes())?;
+            }
+        }
+    }
+    w.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn framebuffer() -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(3, 2);
+        for y in 0..2 {
+            for x in 0..3 {
+                let i = (y * 3 + x) as f32;
+                framebuffer.set(x, y, Vec3 { x: i * 0.25, y: 1. / (i + 1.), z: 40. + i });
+            }
+        }
+        framebuffer
+    }
+
+    // Saves the framebuffer and returns what was written.
+    fn save_as(name: &str, framebuffer: &Framebuffer) -> Vec<u8> {
+        let dir = TempDir::new(name);
+        let path = dir.path().join(name);
+        save(&path, framebuffer, &ToneMapping::default()).unwrap();
+        fs::read(&path).unwrap()
+    }
+
+    fn read_back(name: &str, framebuffer: &Framebuffer) -> Rgb32FImage {
+        let bytes = save_as(name, framebuffer);
+        image::load_from_memory(&bytes).unwrap().into_rgb32f()
+    }
+
+    #[test]
+    fn writes_pfm_bottom_to_top() {
+        let framebuffer = framebuffer();
+        let bytes = save_as("out.pfm", &framebuffer);
+        let header = b"PF\n3 2\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+
+        let floats: Vec<f32> = bytes[header.len()..]
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        assert_eq!(floats.len(), 3 * 2 * 3);
+        for (i, c) in floats.chunks_exact(3).enumerate() {
+            let (x, y) = (i as u32 % 3, 1 - i as u32 / 3);
+            let expected = framebuffer.get(x, y);
+            assert_eq!(c, [expected.x, expected.y, expected.z]);
+        }
+    }
+
+    #[test]
+    fn round_trips_exr_exactly() {
+        let framebuffer = framebuffer();
+        let image = read_back("out.exr", &framebuffer);
+        for (x, y, p) in image.enumerate_pixels() {
+            let expected = framebuffer.get(x, y);
+            assert_eq!(p.0, [expected.x, expected.y, expected.z]);
+        }
+    }
+
+    #[test]
+    fn round_trips_hdr_within_its_precision() {
+        let framebuffer = framebuffer();
+        let image = read_back("out.hdr", &framebuffer);
+        for (x, y, p) in image.enumerate_pixels() {
+            let expected = framebuffer.get(x, y);
+            // shared exponents leave 8 bits of mantissa for the largest channel
+            let tolerance = expected.max_component() / 128.;
+            for (a, b) in p.0.iter().zip([expected.x, expected.y, expected.z]) {
+                assert!((a - b).abs() <= tolerance, "{a} vs {b}");
+            }
+        }
+    }
+}
@@ -1,11 +1,13 @@
 use fastrand::Rng;
 
-use crate::vec::Vec3;
+use crate::{sampling, vec::Vec3};
 
 #[derive(Clone, Copy, Debug)]
 pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
+    // when the ray was sent, between the camera's shutter open and close times
+    pub time: f32,
 }
 
 impl Ray {
@@ -22,6 +24,8 @@ pub struct Camera {
     pub v: Vec3,
     pub w: Vec3,
     pub lens_radius: f32,
+    pub shutter_open: f32,
+    pub shutter_close: f32,
 }
 
 impl Camera {
@@ -56,31 +60,30 @@ impl Camera {
             v,
             w,
             lens_radius: aperture / 2.,
+            shutter_open: 0.,
+            shutter_close: 0.,
         }
     }
 
-    fn random_in_unit_disk(rng: &mut Rng) -> Vec3 {
-        let v = Vec3 { x: 1., y: 1., z: 0. };
-
-        let mut p: Vec3;
-        while {
-            let rnd = Vec3 { x: rng.f32(), y: rng.f32(), z: 0. };
-            p = rnd * 2. - v;
-
-            p.dot(p) >= 1.
-        } {}
-
-        p
+    // Sends rays at random times between `open` and `close`, for motion blur.
+    pub fn with_shutter(self, open: f32, close: f32) -> Self {
+        Camera { shutter_open: open, shutter_close: close, ..self }
     }
 
     pub fn ray(&self, s: f32, t: f32, rng: &mut Rng) -> Ray {
-        let rd = Camera::random_in_unit_disk(rng) * self.lens_radius;
+        let rd = sampling::concentric_disk(rng) * self.lens_radius;
         let offset = self.u * rd.x + self.v * rd.y;
+        let time = if self.shutter_close > self.shutter_open {
+            self.shutter_open + rng.f32() * (self.shutter_close - self.shutter_open)
+        } else {
+            self.shutter_open
+        };
         Ray {
             origin: self.origin + offset,
             direction: self.lower_left_corner + self.horizontal * s + self.vertical * t
                 - self.origin
                 - offset,
+            time,
         }
     }
 }
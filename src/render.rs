@@ -0,0 +1,230 @@
+use std::{
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    bvh::Bvh,
+    light::Lights,
+    ray::Camera,
+    shape::Shapes,
+    tile::{self, TileOrder},
+    tracer::{Background, Tracer},
+    vec::Vec3,
+};
+
+#[derive(Clone, Debug)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    pub rays_per_pixel: u32,
+    pub max_bounces: u32,
+    // bounces before paths can be terminated by Russian roulette
+    pub rr_depth: u32,
+    pub tile_size: u32,
+    pub tile_order: TileOrder,
+    pub seed: u64,
+    pub background: Background,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            width: 2048,
+            height: 1024,
+            rays_per_pixel: 100,
+            max_bounces: 50,
+            rr_depth: 3,
+            tile_size: 16,
+            tile_order: TileOrder::Spiral,
+            seed: 0,
+            background: Background::SKY,
+        }
+    }
+}
+
+// Linear radiance in rows from top to bottom.
+#[derive(Clone, Debug)]
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<Vec3>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Framebuffer {
+        Framebuffer { width, height, pixels: vec![Vec3::ZERO; (width * height) as usize] }
+    }
+
+    pub fn width(&self) -> u32 { self.width }
+
+    pub fn height(&self) -> u32 { self.height }
+
+    pub fn get(&self, x: u32, y: u32) -> Vec3 { self.pixels[(y * self.width + x) as usize] }
+
+    pub fn set(&mut self, x: u32, y: u32, c: Vec3) {
+        self.pixels[(y * self.width + x) as usize] = c;
+    }
+
+    pub fn pixels(&self) -> &[Vec3] { &self.pixels }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Progress {
+    pub tiles_done: usize,
+    pub tiles_total: usize,
+    // camera rays
+    pub rays_traced: usize,
+    // all rays along the paths, excluding shadow rays
+    pub segments_traced: usize,
+    pub elapsed: Duration,
+}
+
+#[derive(Debug)]
+pub struct Renderer<'a> {
+    camera: Camera,
+    world: Bvh<'a>,
+    lights: Lights<'a>,
+    settings: RenderSettings,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(
+        camera: Camera,
+        shapes: Shapes<'a>,
+        lights: Lights<'a>,
+        settings: RenderSettings,
+    ) -> Renderer<'a> {
+        Renderer { camera, world: Bvh::new(shapes), lights, settings }
+    }
+
+    pub fn settings(&self) -> &RenderSettings { &self.settings }
+
+    pub fn shape_count(&self) -> usize { self.world.size() }
+
+    pub fn light_count(&self) -> usize { self.lights.size() }
+
+    pub fn render(&self) -> Framebuffer {
+        self.render_with(|_| {}, &AtomicBool::new(false)).expect("render can't be cancelled")
+    }
+
+    // Renders the image, calling `on_progress` from the worker threads after every
+    // finished tile. Returns None if `cancel` gets set before all tiles are done.
+    pub fn render_with<F>(&self, on_progress: F, cancel: &AtomicBool) -> Option<Framebuffer>
+    where
+        F: Fn(&Progress) + Sync,
+    {
+        let s = &self.settings;
+        let tracer = Tracer::new(&self.camera, &self.world, &self.lights, s);
+
+        let tiles = tile::tiles(s.width, s.height, s.tile_size, s.tile_order);
+        let framebuffer = Mutex::new(Framebuffer::new(s.width, s.height));
+
+        let ray_counter = AtomicUsize::new(0);
+        let segment_counter = AtomicUsize::new(0);
+        let next_tile = AtomicUsize::new(0);
+        let tile_counter = AtomicUsize::new(0);
+
+        let start_time = Instant::now();
+        // every worker keeps taking the next tile in order until none are left
+        rayon::broadcast(|_| {
+            let mut pixels = Vec::new();
+            while let Some(tile) = tiles.get(next_tile.fetch_add(1, Ordering::Relaxed)) {
+                if cancel.load(Ordering::Relaxed) {
+                    return;
+                }
+
+                pixels.clear();
+                let mut segments = 0;
+                for y in tile.y..tile.y + tile.height {
+                    for x in tile.x..tile.x + tile.width {
+                        let y = s.height - y - 1; // tracer renders bottom to top
+                        let mut rng = crate::pixel_rng(s.seed, x, y);
+                        let (color, rays) = tracer.trace_pixel(x, y, s.rays_per_pixel, &mut rng);
+                        pixels.push(color);
+                        segments += rays as usize;
+                    }
+                }
+
+                {
+                    let mut framebuffer = framebuffer.lock().unwrap();
+                    let rows = pixels.chunks(tile.width as usize);
+                    for (y, row) in (tile.y..).zip(rows) {
+                        let start = (y * s.width + tile.x) as usize;
+                        framebuffer.pixels[start..start + row.len()].copy_from_slice(row);
+                    }
+                }
+
+                let rays_in_this_tile = pixels.len() * s.rays_per_pixel as usize;
+                on_progress(&Progress {
+                    tiles_done: tile_counter.fetch_add(1, Ordering::Relaxed) + 1,
+                    tiles_total: tiles.len(),
+                    rays_traced: ray_counter.fetch_add(rays_in_this_tile, Ordering::Relaxed)
+                        + rays_in_this_tile,
+                    segments_traced: segment_counter.fetch_add(segments, Ordering::Relaxed)
+                        + segments,
+                    elapsed: start_time.elapsed(),
+                });
+            }
+        });
+
+        if cancel.load(Ordering::Relaxed) && tile_counter.load(Ordering::Relaxed) < tiles.len() {
+            return None;
+        }
+
+        Some(framebuffer.into_inner().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fastrand::Rng;
+
+    use super::*;
+    use crate::scene::Scene;
+
+    fn render(scene: fn(&mut Rng) -> Scene, tile_size: u32, tile_order: TileOrder) -> Framebuffer {
+        let scene = scene(&mut Rng::with_seed(3));
+        let settings = RenderSettings {
+            width: 48,
+            height: 32,
+            rays_per_pixel: 4,
+            tile_size,
+            tile_order,
+            seed: 5,
+            background: scene.background,
+            ..RenderSettings::default()
+        };
+        let camera = scene.camera.camera(1.5);
+        Renderer::new(camera, scene.shapes, scene.lights, settings).render()
+    }
+
+    fn assert_reproducible(scene: fn(&mut Rng) -> Scene) {
+        let single_thread = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+        let a = single_thread.install(|| render(scene, 7, TileOrder::Scanline));
+        let b = render(scene, 16, TileOrder::Hilbert);
+        let c = render(scene, 5, TileOrder::Spiral);
+        for (other, name) in [(&b, "hilbert"), (&c, "spiral")] {
+            for (p, q) in a.pixels().iter().zip(other.pixels()) {
+                assert_eq!(
+                    [p.x, p.y, p.z].map(f32::to_bits),
+                    [q.x, q.y, q.z].map(f32::to_bits),
+                    "{name}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn renders_are_independent_of_tiles_and_threads() {
+        assert_reproducible(|rng| Scene::random_spheres(40, 2, rng));
+    }
+
+    #[test]
+    fn renders_with_media_are_independent_of_tiles_and_threads() {
+        assert_reproducible(|_| Scene::cornell_smoke());
+    }
+}
@@ -0,0 +1,215 @@
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+use fastrand::Rng;
+
+use crate::vec::Vec3;
+
+// Orthonormal basis around the unit vector `w`, used to move directions sampled
+// around the z axis into place.
+#[derive(Clone, Copy, Debug)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn new(w: Vec3) -> Onb {
+        let (u, v) = w.orthonormal_basis();
+        Onb { u, v, w }
+    }
+
+    #[inline]
+    pub fn local(&self, a: Vec3) -> Vec3 { self.u * a.x + self.v * a.y + self.w * a.z }
+}
+
+// Maps the unit square onto the unit disk, keeping neighbouring samples close
+// and areas proportional. Returns a point with z = 0.
+pub fn concentric_disk(rng: &mut Rng) -> Vec3 {
+    let (a, b) = (rng.f32() * 2. - 1., rng.f32() * 2. - 1.);
+    if a == 0. && b == 0. {
+        return Vec3::ZERO;
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, FRAC_PI_4 * (b / a))
+    } else {
+        (b, FRAC_PI_2 - FRAC_PI_4 * (a / b))
+    };
+    Vec3 { x: r * theta.cos(), y: r * theta.sin(), z: 0. }
+}
+
+// Directions around +z with a density proportional to their cosine, see
+// `cosine_hemisphere_pdf`.
+pub fn cosine_hemisphere(rng: &mut Rng) -> Vec3 {
+    let d = concentric_disk(rng);
+    Vec3 { z: (1. - d.x * d.x - d.y * d.y).max(0.).sqrt(), ..d }
+}
+
+#[inline]
+pub fn cosine_hemisphere_pdf(cos_theta: f32) -> f32 { cos_theta.max(0.) / PI }
+
+pub fn uniform_sphere(rng: &mut Rng) -> Vec3 {
+    let z = 1. - 2. * rng.f32();
+    let r = (1. - z * z).max(0.).sqrt();
+    let phi = 2. * PI * rng.f32();
+    Vec3 { x: r * phi.cos(), y: r * phi.sin(), z }
+}
+
+#[inline]
+pub fn uniform_sphere_pdf() -> f32 { 1. / (4. * PI) }
+
+// Barycentric weights of a point uniformly distributed over a triangle.
+pub fn uniform_triangle(rng: &mut Rng) -> [f32; 3] {
+    let s = rng.f32().sqrt();
+    let (b0, b1) = (1. - s, rng.f32() * s);
+    [b0, b1, 1. - b0 - b1]
+}
+
+// A point uniformly distributed within the unit ball.
+pub fn uniform_ball(rng: &mut Rng) -> Vec3 { uniform_sphere(rng) * rng.f32().cbrt() }
+
+// Density of the direction of the unit `axis` plus a uniform point in a ball of
+// `radius` up to 1, see `uniform_ball`, picking the unit `direction`. It's the
+// length of the chord the direction cuts through the ball, weighted by the
+// squared distance along it.
+pub fn offset_ball_pdf(axis: Vec3, direction: Vec3, radius: f32) -> f32 {
+    let b = axis.dot(direction);
+    // the sine from the cross product, which doesn't cancel for small angles
+    let discriminant = radius * radius - axis.cross(direction).squared_length();
+    if b <= 0. || discriminant <= 0. {
+        return 0.;
+    }
+    let half_chord = discriminant.sqrt();
+    let (t1, t2) = ((b - half_chord).max(0.), b + half_chord);
+    (t2 - t1) * (t2 * t2 + t2 * t1 + t1 * t1) / (4. * PI * radius.powi(3))
+}
+
+// A piecewise-constant density over [0, 1) with one step per value of a
+// non-negative function, sampled by inverting its CDF.
+#[derive(Clone, Debug)]
+pub struct Distribution1D {
+    func: Vec<f32>,
+    cdf: Vec<f32>,
+    integral: f32,
+}
+
+impl Distribution1D {
+    pub fn new(func: Vec<f32>) -> Distribution1D {
+        let n = func.len() as f32;
+        let mut cdf = Vec::with_capacity(func.len() + 1);
+        cdf.push(0.);
+        for (i, f) in func.iter().enumerate() {
+            cdf.push(cdf[i] + f / n);
+        }
+        let integral = cdf[func.len()];
+        for (i, c) in cdf.iter_mut().enumerate() {
+            // an all-zero function falls back to a uniform density
+            *c = if integral > 0. { *c / integral } else { i as f32 / n };
+        }
+        Distribution1D { func, cdf, integral }
+    }
+
+    pub fn integral(&self) -> f32 { self.integral }
+
+    // Maps `u` in [0, 1) to a sample in [0, 1), returning it with its density and
+    // the index of its step.
+    pub fn sample(&self, u: f32) -> (f32, f32, usize) {
+        let n = self.func.len();
+        let i = (self.cdf.partition_point(|&c| c <= u) - 1).min(n - 1);
+        let width = self.cdf[i + 1] - self.cdf[i];
+        let du = if width > 0. { (u - self.cdf[i]) / width } else { 0. };
+        let x = ((i as f32 + du) / n as f32).min(1. - f32::EPSILON);
+        (x, self.pdf_at(i), i)
+    }
+
+    pub fn pdf(&self, x: f32) -> f32 { self.pdf_at(self.index(x)) }
+
+    fn pdf_at(&self, i: usize) -> f32 {
+        if self.integral > 0. { self.func[i] / self.integral } else { 1. }
+    }
+
+    fn index(&self, x: f32) -> usize {
+        ((x * self.func.len() as f32) as usize).min(self.func.len() - 1)
+    }
+}
+
+// A piecewise-constant density over [0, 1)^2 from a grid of `width` by `height`
+// values in rows, sampled by picking a row from the marginal density and then a
+// column within it.
+#[derive(Clone, Debug)]
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(func: &[f32], width: usize, height: usize) -> Distribution2D {
+        assert_eq!(func.len(), width * height);
+        let rows: Vec<_> =
+            func.chunks_exact(width).map(|row| Distribution1D::new(row.to_vec())).collect();
+        let marginal = Distribution1D::new(rows.iter().map(Distribution1D::integral).collect());
+        Distribution2D { rows, marginal }
+    }
+
+    // Returns a point in [0, 1)^2 with its density.
+    pub fn sample(&self, rng: &mut Rng) -> ((f32, f32), f32) {
+        let (v, pdf_v, row) = self.marginal.sample(rng.f32());
+        let (u, pdf_u, _) = self.rows[row].sample(rng.f32());
+        ((u, v), pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, u: f32, v: f32) -> f32 {
+        let row = self.marginal.index(v);
+        self.marginal.pdf_at(row) * self.rows[row].pdf(u)
+    }
+}
+
+// Directions around +z within a cone, uniform in solid angle. The cone is given
+// by 1 - cos of its half angle, which keeps narrow cones precise.
+pub fn uniform_cone(rng: &mut Rng, cone_height: f32) -> Vec3 {
+    let h = rng.f32() * cone_height;
+    let sin_theta = (h * (2. - h)).max(0.).sqrt();
+    let phi = 2. * PI * rng.f32();
+    Vec3 { x: sin_theta * phi.cos(), y: sin_theta * phi.sin(), z: 1. - h }
+}
+
+#[inline]
+pub fn uniform_cone_pdf(cone_height: f32) -> f32 { 1. / (2. * PI * cone_height) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offset_ball_pdf_matches_the_samples() {
+        let mut rng = Rng::with_seed(9);
+        let axis = Vec3 { x: 0.3, y: -0.5, z: 0.8 }.unit();
+        let onb = Onb::new(axis);
+        for radius in [0.02f32, 0.3, 1.] {
+            // every direction within reach of the ball lies in this cone
+            let cone_height = radius * radius / (1. + (1. - radius * radius).sqrt());
+            let n = 200_000;
+            let (mut total, mut spread) = (0., 0.);
+            for _ in 0..n {
+                let direction = onb.local(uniform_cone(&mut rng, cone_height));
+                let weight =
+                    offset_ball_pdf(axis, direction, radius) / uniform_cone_pdf(cone_height);
+                total += weight;
+                spread += weight * (1. - axis.dot(direction));
+            }
+            assert!((total / n as f32 - 1.).abs() < 0.02, "radius {radius}: {}", total / n as f32);
+
+            // the average 1 - cos to the axis, integrated against the pdf and from
+            // the samples themselves
+            let expected = spread / n as f32;
+            let sampled = (0..n)
+                .map(|_| 1. - axis.dot((axis + uniform_ball(&mut rng) * radius).unit()))
+                .sum::<f32>()
+                / n as f32;
+            assert!(
+                (sampled / expected - 1.).abs() < 0.02,
+                "radius {radius}: {sampled} vs {expected}"
+            );
+        }
+    }
+}
@@ -0,0 +1,913 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fmt, fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use fastrand::Rng;
+use image::ImageError;
+use serde::{
+    Deserialize, Deserializer,
+    de::{
+        self, MapAccess, SeqAccess, Visitor,
+        value::{MapAccessDeserializer, SeqAccessDeserializer},
+    },
+};
+use toml::Spanned;
+
+use crate::{
+    aabb::Aabb,
+    environment::EnvironmentMap,
+    light::{DirectionalLight, Lights, PointLight, SpotLight},
+    material::{Dielectric, DiffuseLight, Lambertian, Material, Metal},
+    medium::{ConstantMedium, HeterogeneousMedium},
+    mesh::{Mesh, MeshLight},
+    noise::Noise,
+    obj::{self, ObjError},
+    ray::Camera,
+    shape::{MovingSphere, Shapes, Sphere},
+    sky::Sky,
+    texture::{Filter, ImageTexture, Pattern, Texture, Wrap},
+    tracer::Background,
+    transform::{Transform, Transformed},
+    vec::Vec3,
+    volume::VoxelGrid,
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct CameraSettings {
+    pub look_from: Vec3,
+    pub look_at: Vec3,
+    pub view_up: Vec3,
+    pub v_fov_deg: f32,
+    pub aperture: f32,
+    pub focus_dist: f32,
+    pub shutter_open: f32,
+    pub shutter_close: f32,
+}
+
+impl CameraSettings {
+    pub fn camera(&self, aspect: f32) -> Camera {
+        Camera::new(
+            self.look_from,
+            self.look_at,
+            self.view_up,
+            self.v_fov_deg,
+            aspect,
+            self.aperture,
+            self.focus_dist,
+        )
+        .with_shutter(self.shutter_open, self.shutter_close)
+    }
+}
+
+#[derive(Debug)]
+pub struct Scene {
+    pub camera: CameraSettings,
+    pub shapes: Shapes<'static>,
+    pub lights: Lights<'static>,
+    pub background: Background,
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(PathBuf, io::Error),
+    Parse { path: PathBuf, line: usize, column: usize, message: String },
+    Obj(ObjError),
+    Image(PathBuf, ImageError),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            SceneError::Parse { path, line, column, message } => {
+                write!(f, "{}:{}:{}: {}", path.display(), line, column, message)
+            }
+            SceneError::Obj(err) => err.fmt(f),
+            SceneError::Image(path, err) => write!(f, "{}: {}", path.display(), err),
+        }
+    }
+}
+
+impl Error for SceneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SceneError::Io(_, err) => Some(err),
+            SceneError::Parse { .. } => None,
+            SceneError::Obj(err) => Some(err),
+            SceneError::Image(_, err) => Some(err),
+        }
+    }
+}
+
+impl From<ObjError> for SceneError {
+    fn from(err: ObjError) -> Self { SceneError::Obj(err) }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    camera: CameraFile,
+    #[serde(default)]
+    background: Option<BackgroundFile>,
+    // sorted, so that noise textures are seeded in the same order on every run
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialFile>,
+    #[serde(default)]
+    spheres: Vec<SphereFile>,
+    #[serde(default)]
+    quads: Vec<QuadFile>,
+    #[serde(default)]
+    meshes: Vec<MeshFile>,
+    #[serde(default)]
+    media: Vec<MediumFile>,
+    #[serde(default)]
+    volumes: Vec<VolumeFile>,
+    #[serde(default)]
+    lights: Vec<LightFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraFile {
+    look_from: [f32; 3],
+    look_at: [f32; 3],
+    #[serde(default = "default_view_up")]
+    view_up: [f32; 3],
+    fov: f32,
+    #[serde(default)]
+    aperture: f32,
+    focus_distance: Option<f32>,
+    #[serde(default)]
+    shutter_open: f32,
+    #[serde(default)]
+    shutter_close: f32,
+}
+
+fn default_view_up() -> [f32; 3] { [0., 1., 0.] }
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum BackgroundFile {
+    Environment {
+        file: String,
+        #[serde(default)]
+        rotation: f32,
+        #[serde(default = "default_intensity")]
+        intensity: f32,
+    },
+    Gradient {
+        bottom: [f32; 3],
+        top: [f32; 3],
+    },
+    Sky {
+        elevation: f32,
+        #[serde(default)]
+        azimuth: f32,
+        #[serde(default = "default_turbidity")]
+        turbidity: f32,
+    },
+    Solid {
+        color: [f32; 3],
+    },
+}
+
+fn default_intensity() -> f32 { 1. }
+
+fn default_turbidity() -> f32 { 3. }
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialFile {
+    Dielectric { ior: f32 },
+    DiffuseLight { radiance: TextureFile },
+    Lambertian { albedo: TextureFile },
+    Metal { albedo: TextureFile, fuzz: TextureFile },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum LightFile {
+    Point {
+        position: [f32; 3],
+        intensity: [f32; 3],
+    },
+    Spot {
+        position: [f32; 3],
+        direction: [f32; 3],
+        intensity: [f32; 3],
+        #[serde(default)]
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+    Directional {
+        direction: [f32; 3],
+        irradiance: [f32; 3],
+        #[serde(default)]
+        angular_diameter: f32,
+    },
+}
+
+// Either a plain color, a gray level or a texture table.
+enum TextureFile {
+    Color([f32; 3]),
+    Gray(f32),
+    Texture(TextureKindFile),
+}
+
+impl<'de> Deserialize<'de> for TextureFile {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TextureVisitor;
+
+        impl<'de> Visitor<'de> for TextureVisitor {
+            type Value = TextureFile;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a color, a number or a texture table")
+            }
+
+            fn visit_f64<E: de::Error>(self, x: f64) -> Result<Self::Value, E> {
+                Ok(TextureFile::Gray(x as f32))
+            }
+
+            fn visit_i64<E: de::Error>(self, x: i64) -> Result<Self::Value, E> {
+                Ok(TextureFile::Gray(x as f32))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+                Deserialize::deserialize(SeqAccessDeserializer::new(seq)).map(TextureFile::Color)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                Deserialize::deserialize(MapAccessDeserializer::new(map)).map(TextureFile::Texture)
+            }
+        }
+
+        deserializer.deserialize_any(TextureVisitor)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureKindFile {
+    Checker {
+        even: Box<TextureFile>,
+        odd: Box<TextureFile>,
+        #[serde(default = "default_checker_scale")]
+        scale: f32,
+    },
+    Image {
+        file: String,
+        #[serde(default = "default_filter", deserialize_with = "from_str")]
+        filter: Filter,
+        #[serde(default = "default_wrap", deserialize_with = "from_str")]
+        wrap: Wrap,
+    },
+    Noise {
+        #[serde(deserialize_with = "from_str")]
+        pattern: Pattern,
+        #[serde(default = "default_noise_scale")]
+        scale: f32,
+        #[serde(default = "default_octaves")]
+        octaves: u32,
+        #[serde(default = "default_low")]
+        low: Box<TextureFile>,
+        #[serde(default = "default_high")]
+        high: Box<TextureFile>,
+    },
+}
+
+fn default_checker_scale() -> f32 { 1. }
+
+fn default_filter() -> Filter { Filter::Bilinear }
+
+fn default_wrap() -> Wrap { Wrap::Repeat }
+
+fn default_noise_scale() -> f32 { 1. }
+
+fn default_octaves() -> u32 { 7 }
+
+fn default_low() -> Box<TextureFile> { Box::new(TextureFile::Gray(0.)) }
+
+fn default_high() -> Box<TextureFile> { Box::new(TextureFile::Gray(1.)) }
+
+fn from_str<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = String>,
+{
+    String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SphereFile {
+    center: [f32; 3],
+    // where a moving sphere is at the shutter close, `center` being its position
+    // at the shutter open
+    center1: Option<[f32; 3]>,
+    radius: f32,
+    material: Spanned<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QuadFile {
+    origin: [f32; 3],
+    u: [f32; 3],
+    v: [f32; 3],
+    material: Spanned<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MeshFile {
+    file: String,
+    // used for faces that don't pick a material from the OBJ's own library
+    material: Spanned<String>,
+    // applied in this order: scale, rotation around x, y then z in degrees,
+    // translation
+    scale: Option<Spanned<ScaleFile>>,
+    rotate: Option<[f32; 3]>,
+    translate: Option<[f32; 3]>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MediumFile {
+    boundary: BoundaryFile,
+    density: f32,
+    albedo: TextureFile,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VolumeFile {
+    file: String,
+    // the box the grid is stretched over
+    min: [f32; 3],
+    max: [f32; 3],
+    #[serde(default = "default_volume_density")]
+    density: f32,
+    #[serde(default = "default_high")]
+    albedo: Box<TextureFile>,
+    #[serde(default)]
+    anisotropy: f32,
+}
+
+fn default_volume_density() -> f32 { 1. }
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum BoundaryFile {
+    Sphere { center: [f32; 3], radius: f32 },
+    Box { min: [f32; 3], max: [f32; 3] },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScaleFile {
+    Uniform(f32),
+    Axes([f32; 3]),
+}
+
+// Image paths are relative to `dir`, the directory of the scene file. Every
+// noise texture gets its own noise seeded from `rng`.
+fn load_texture(t: TextureFile, dir: &Path, rng: &mut Rng) -> Result<Texture, SceneError> {
+    let kind = match t {
+        TextureFile::Color(color) => return Ok(Texture::Solid(color.into())),
+        TextureFile::Gray(x) => return Ok(x.into()),
+        TextureFile::Texture(kind) => kind,
+    };
+    match kind {
+        TextureKindFile::Checker { even, odd, scale } => Ok(Texture::Checker {
+            even: Box::new(load_texture(*even, dir, rng)?),
+            odd: Box::new(load_texture(*odd, dir, rng)?),
+            scale,
+        }),
+        TextureKindFile::Image { file, filter, wrap } => {
+            let image_path = dir.join(file);
+            let image = ImageTexture::load(&image_path, filter, wrap)
+                .map_err(|e| SceneError::Image(image_path, e))?;
+            Ok(Texture::Image(Arc::new(image)))
+        }
+        TextureKindFile::Noise { pattern, scale, octaves, low, high } => Ok(Texture::Noise {
+            noise: Arc::new(Noise::new(rng)),
+            pattern,
+            scale,
+            octaves,
+            low: Box::new(load_texture(*low, dir, rng)?),
+            high: Box::new(load_texture(*high, dir, rng)?),
+        }),
+    }
+}
+
+impl Scene {
+    // `rng` seeds the noise textures.
+    pub fn load<P: AsRef<Path>>(path: P, rng: &mut Rng) -> Result<Scene, SceneError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|e| SceneError::Io(path.to_owned(), e))?;
+        Scene::parse(&source, path, rng)
+    }
+
+    // Parses a scene read from `path`, which names it in errors and locates the
+    // files it refers to.
+    pub fn parse(source: &str, path: &Path, rng: &mut Rng) -> Result<Scene, SceneError> {
+        let parse_error = |span: Option<Range<usize>>, message: String| {
+            let offset = span.map(|s| s.start).unwrap_or(0).min(source.len());
+            let before = &source[..offset];
+            let line = before.matches('\n').count() + 1;
+            let column = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+            SceneError::Parse { path: path.to_owned(), line, column, message }
+        };
+
+        let file: SceneFile =
+            toml::from_str(source).map_err(|e| parse_error(e.span(), e.message().to_owned()))?;
+
+        // other files are looked up relative to the scene file
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let mut texture = |t| load_texture(t, dir, rng);
+        let mut materials = HashMap::new();
+        for (name, m) in file.materials {
+            let m: Arc<dyn Material> = match m {
+                MaterialFile::Dielectric { ior } => Arc::new(Dielectric(ior)),
+                MaterialFile::DiffuseLight { radiance } => {
+                    Arc::new(DiffuseLight(texture(radiance)?))
+                }
+                MaterialFile::Lambertian { albedo } => Arc::new(Lambertian(texture(albedo)?)),
+                MaterialFile::Metal { albedo, fuzz } => {
+                    Arc::new(Metal(texture(albedo)?, texture(fuzz)?))
+                }
+            };
+            materials.insert(name, m);
+        }
+        let material = |name: &Spanned<String>| {
+            materials.get(name.get_ref()).map(Arc::clone).ok_or_else(|| {
+                parse_error(Some(name.span()), format!("unknown material '{}'", name.get_ref()))
+            })
+        };
+
+        let look_from = Vec3::from(file.camera.look_from);
+        let look_at = Vec3::from(file.camera.look_at);
+        let camera = CameraSettings {
+            look_from,
+            look_at,
+            view_up: file.camera.view_up.into(),
+            v_fov_deg: file.camera.fov,
+            aperture: file.camera.aperture,
+            focus_dist: file.camera.focus_distance.unwrap_or((look_from - look_at).length()),
+            shutter_open: file.camera.shutter_open,
+            shutter_close: file.camera.shutter_close,
+        };
+
+        let mut shapes = Shapes::new();
+        let mut lights = Lights::new();
+
+        let background = match file.background {
+            Some(BackgroundFile::Environment { file, rotation, intensity }) => {
+                let map_path = dir.join(file);
+                let map = EnvironmentMap::load(&map_path, rotation, intensity)
+                    .map_err(|e| SceneError::Image(map_path, e))?;
+                let map = Arc::new(map);
+                lights.add(Arc::clone(&map));
+                Background::Environment(map)
+            }
+            Some(BackgroundFile::Gradient { bottom, top }) => {
+                Background::Gradient { bottom: bottom.into(), top: top.into() }
+            }
+            Some(BackgroundFile::Sky { elevation, azimuth, turbidity }) => {
+                let sky = Arc::new(Sky::new(elevation, azimuth, turbidity));
+                lights.add(Arc::clone(&sky));
+                Background::Sky(sky)
+            }
+            Some(BackgroundFile::Solid { color }) => Background::Solid(color.into()),
+            None => Background::SKY,
+        };
+
+        for sphere in &file.spheres {
+            if let Some(center1) = sphere.center1 {
+                // moving lights aren't sampled directly, only found by chance
+                shapes.add(MovingSphere {
+                    center0: sphere.center.into(),
+                    center1: center1.into(),
+                    time0: camera.shutter_open,
+                    time1: camera.shutter_close,
+                    radius: sphere.radius,
+                    material: material(&sphere.material)?,
+                });
+                continue;
+            }
+            let sphere = Sphere {
+                center: sphere.center.into(),
+                radius: sphere.radius,
+                material: material(&sphere.material)?,
+            };
+            if sphere.material.is_emissive() {
+                lights.add(sphere.clone());
+            }
+            shapes.add(sphere);
+        }
+        for quad in &file.quads {
+            let material = material(&quad.material)?;
+            let quad = Mesh::quad(quad.origin.into(), quad.u.into(), quad.v.into(), material);
+            if let Some(light) = MeshLight::new(&quad, &Transform::IDENTITY) {
+                lights.add(light);
+            }
+            shapes.add(quad);
+        }
+        // meshes listed several times with the same material are loaded once and
+        // instanced
+        let mut loaded: HashMap<(&str, &str), Arc<Mesh>> = HashMap::new();
+        for mesh in &file.meshes {
+            let key = (mesh.file.as_str(), mesh.material.get_ref().as_str());
+            let shape = match loaded.get(&key) {
+                Some(shape) => Arc::clone(shape),
+                None => {
+                    let shape = obj::load(dir.join(&mesh.file), material(&mesh.material)?)?;
+                    Arc::clone(loaded.entry(key).or_insert(Arc::new(shape)))
+                }
+            };
+
+            let mut transform = Transform::IDENTITY;
+            if let Some(scale) = &mesh.scale {
+                let s = match *scale.get_ref() {
+                    ScaleFile::Uniform(s) => Vec3 { x: s, y: s, z: s },
+                    ScaleFile::Axes(s) => s.into(),
+                };
+                if s.x == 0. || s.y == 0. || s.z == 0. {
+                    return Err(parse_error(Some(scale.span()), "scale by 0".to_owned()));
+                }
+                transform = Transform::scale(s);
+            }
+            if let Some([x, y, z]) = mesh.rotate {
+                transform = Transform::rotate(Vec3 { x: 1., y: 0., z: 0. }, x) * transform;
+                transform = Transform::rotate(Vec3 { x: 0., y: 1., z: 0. }, y) * transform;
+                transform = Transform::rotate(Vec3 { x: 0., y: 0., z: 1. }, z) * transform;
+            }
+            if let Some(t) = mesh.translate {
+                transform = Transform::translate(t.into()) * transform;
+            }
+            // every instance of an emissive mesh is a light of its own
+            if let Some(light) = MeshLight::new(&shape, &transform) {
+                lights.add(light);
+            }
+            if transform == Transform::IDENTITY {
+                shapes.add(shape);
+            } else {
+                shapes.add(Transformed::new(shape, transform));
+            }
+        }
+        for medium in file.media {
+            let albedo = texture(medium.albedo)?;
+            // the boundary's material is never seen, the medium replaces it
+            let unused = Arc::new(Dielectric(1.));
+            match medium.boundary {
+                BoundaryFile::Sphere { center, radius } => {
+                    let sphere = Sphere { center: center.into(), radius, material: unused };
+                    shapes.add(ConstantMedium::new(sphere, medium.density, albedo));
+                }
+                BoundaryFile::Box { min, max } => {
+                    let cuboid = Mesh::cuboid(min.into(), max.into(), unused);
+                    shapes.add(ConstantMedium::new(cuboid, medium.density, albedo));
+                }
+            }
+        }
+        let mut grids: HashMap<String, Arc<VoxelGrid>> = HashMap::new();
+        for volume in file.volumes {
+            let grid = match grids.get(volume.file.as_str()) {
+                Some(grid) => Arc::clone(grid),
+                None => {
+                    let grid_path = dir.join(&volume.file);
+                    let grid = VoxelGrid::load(&grid_path)
+                        .map_err(|e| SceneError::Io(grid_path.clone(), e))?;
+                    Arc::clone(grids.entry(volume.file.clone()).or_insert(Arc::new(grid)))
+                }
+            };
+            shapes.add(HeterogeneousMedium::new(
+                grid,
+                Aabb::new(volume.min.into(), volume.max.into()),
+                volume.density,
+                texture(*volume.albedo)?,
+                volume.anisotropy,
+            ));
+        }
+        for light in file.lights {
+            match light {
+                LightFile::Point { position, intensity } => {
+                    let (position, intensity) = (position.into(), intensity.into());
+                    lights.add(PointLight { position, intensity });
+                }
+                LightFile::Spot { position, direction, intensity, inner_angle, outer_angle } => {
+                    let (position, direction) = (position.into(), direction.into());
+                    let intensity = intensity.into();
+                    lights.add(SpotLight::new(
+                        position,
+                        direction,
+                        intensity,
+                        inner_angle,
+                        outer_angle,
+                    ));
+                }
+                LightFile::Directional { direction, irradiance, angular_diameter } => {
+                    let (direction, irradiance) = (direction.into(), irradiance.into());
+                    lights.add(DirectionalLight::new(direction, irradiance, angular_diameter));
+                }
+            }
+        }
+
+        Ok(Scene { camera, shapes, lights, background })
+    }
+
+    // Lights the scene with daylight instead of its background.
+    pub fn set_sky(&mut self, sky: Sky) {
+        let sky = Arc::new(sky);
+        self.lights.add(Arc::clone(&sky));
+        self.background = Background::Sky(sky);
+    }
+
+    // The random sphere field from the cover of "Ray Tracing in One Weekend", with
+    // `max_lights` emissive spheres hovering above it.
+    pub fn random_spheres(max_spheres: u32, max_lights: u32, rng: &mut Rng) -> Scene {
+        Scene::sphere_field(max_spheres, max_lights, false, rng)
+    }
+
+    // The same field with the diffuse spheres bouncing up while the shutter is
+    // open, as in "Ray Tracing: The Next Week".
+    pub fn bouncing_spheres(max_spheres: u32, max_lights: u32, rng: &mut Rng) -> Scene {
+        Scene::sphere_field(max_spheres, max_lights, true, rng)
+    }
+
+    fn sphere_field(max_spheres: u32, max_lights: u32, bounce: bool, rng: &mut Rng) -> Scene {
+        let mut s = Shapes::new();
+        let mut l = Lights::new();
+        s.add(Sphere {
+            center: Vec3 { x: 0., y: -1000., z: 0. },
+            radius: 1000.,
+            material: Arc::new(Lambertian(Vec3 { x: 0.5, y: 0.5, z: 0.5 }.into())),
+        });
+
+        let middle = Vec3 { x: 4., y: 0.2, z: 0. };
+
+        let ab_range = {
+            let range_len = f64::from(max_spheres).sqrt().floor() as i32;
+            let from = 0 - (range_len / 2);
+            let to = from + range_len;
+            from..to
+        };
+        for a in ab_range.clone() {
+            for b in ab_range.clone() {
+                let center =
+                    Vec3 { x: a as f32 + rng.f32() * 0.9, y: 0.2, z: b as f32 + rng.f32() * 0.9 };
+
+                if (center - middle).length() > 0.9 {
+                    let rnd_material = rng.u8(0..=100);
+                    match rnd_material {
+                        0..=79 => {
+                            // diffuse
+                            let rnd_albedo = Vec3 {
+                                x: rng.f32() * rng.f32(),
+                                y: rng.f32() * rng.f32(),
+                                z: rng.f32() * rng.f32(),
+                            };
+                            let material = Arc::new(Lambertian(rnd_albedo.into()));
+                            if bounce {
+                                s.add(MovingSphere {
+                                    center0: center,
+                                    center1: center + Vec3 { x: 0., y: rng.f32() * 0.5, z: 0. },
+                                    time0: 0.,
+                                    time1: 1.,
+                                    radius: 0.2,
+                                    material,
+                                });
+                            } else {
+                                s.add(Sphere { center, radius: 0.2, material });
+                            }
+                        }
+                        80..=94 => {
+                            // metal
+                            let albedo = Vec3 {
+                                x: 0.5 * (1. + rng.f32()),
+                                y: 0.5 * (1. + rng.f32()),
+                                z: 0.5 * (1. + rng.f32()),
+                            };
+                            let fuzz = 0.5 * rng.f32();
+                            s.add(Sphere {
+                                center,
+                                radius: 0.2,
+                                material: Arc::new(Metal(albedo.into(), fuzz.into())),
+                            });
+                        }
+                        95..=100 => {
+                            // glass
+                            s.add(Sphere {
+                                center,
+                                radius: 0.2,
+                                material: Arc::new(Dielectric(1.5)),
+                            });
+                        }
+                        _ => unreachable!(),
+                    }
+                }
+            }
+        }
+
+        s.add(Sphere {
+            center: Vec3 { x: 0., y: 1., z: 0. },
+            radius: 1.,
+            material: Arc::new(Dielectric(1.5)),
+        });
+        s.add(Sphere {
+            center: Vec3 { x: -4., y: 1., z: 0. },
+            radius: 1.,
+            material: Arc::new(Lambertian(Vec3 { x: 0.4, y: 0.2, z: 0.1 }.into())),
+        });
+        s.add(Sphere {
+            center: Vec3 { x: 4., y: 1., z: 0. },
+            radius: 1.,
+            material: Arc::new(Metal(Vec3 { x: 0.7, y: 0.6, z: 0.5 }.into(), 0.0.into())),
+        });
+
+        for _ in 0..max_lights {
+            let center =
+                Vec3 { x: rng.f32() * 16. - 8., y: 2. + rng.f32() * 2., z: rng.f32() * 8. - 4. };
+            let radiance = Vec3 { x: 1. + rng.f32(), y: 1. + rng.f32(), z: 1. + rng.f32() } * 8.;
+            let light =
+                Sphere { center, radius: 0.3, material: Arc::new(DiffuseLight(radiance.into())) };
+            l.add(light.clone());
+            s.add(light);
+        }
+
+        #[rustfmt::skip]
+        let camera = CameraSettings {
+            look_from: Vec3 { x: 13., y: 2., z:  3. },
+            look_at:   Vec3 { x:  0., y: 0., z:  0. },
+            view_up:   Vec3 { x:  0., y: 1., z:  0. },
+            v_fov_deg: 30.,
+            aperture: 0.1,
+            focus_dist: 10.,
+            shutter_open: 0.,
+            shutter_close: if bounce { 1. } else { 0. },
+        };
+
+        Scene { camera, shapes: s, lights: l, background: Background::SKY }
+    }
+
+    pub fn cornell_box() -> Scene {
+        let (mut s, l) = Scene::cornell_room();
+        s.add(Sphere {
+            center: Vec3 { x: 190., y: 90., z: 190. },
+            radius: 90.,
+            material: Arc::new(Dielectric(1.5)),
+        });
+        s.add(Sphere {
+            center: Vec3 { x: 370., y: 120., z: 370. },
+            radius: 120.,
+            material: Arc::new(Metal(Vec3 { x: 0.8, y: 0.85, z: 0.88 }.into(), 0.05.into())),
+        });
+        Scene::cornell_scene(s, l)
+    }
+
+    // The Cornell box with two blocks of dark and light smoke, as in "Ray Tracing:
+    // The Next Week".
+    pub fn cornell_smoke() -> Scene {
+        let (mut s, l) = Scene::cornell_room();
+        let white = Arc::new(Lambertian(Vec3::ONE.into()));
+
+        let tall = Mesh::cuboid(Vec3::ZERO, Vec3 { x: 165., y: 330., z: 165. }, white.clone());
+        let tall = Transformed::new(
+            tall,
+            Transform::translate(Vec3 { x: 265., y: 0., z: 295. })
+                * Transform::rotate(Vec3 { x: 0., y: 1., z: 0. }, 15.),
+        );
+        s.add(ConstantMedium::new(tall, 0.01, Vec3::ZERO.into()));
+
+        let short = Mesh::cuboid(Vec3::ZERO, Vec3 { x: 165., y: 165., z: 165. }, white);
+        let short = Transformed::new(
+            short,
+            Transform::translate(Vec3 { x: 130., y: 0., z: 65. })
+                * Transform::rotate(Vec3 { x: 0., y: 1., z: 0. }, -18.),
+        );
+        s.add(ConstantMedium::new(short, 0.01, Vec3::ONE.into()));
+
+        Scene::cornell_scene(s, l)
+    }
+
+    fn cornell_room() -> (Shapes<'static>, Lights<'static>) {
+        let red = Arc::new(Lambertian(Vec3 { x: 0.65, y: 0.05, z: 0.05 }.into()));
+        let white = Arc::new(Lambertian(Vec3 { x: 0.73, y: 0.73, z: 0.73 }.into()));
+        let green = Arc::new(Lambertian(Vec3 { x: 0.12, y: 0.45, z: 0.15 }.into()));
+        let light = Arc::new(DiffuseLight(Vec3 { x: 15., y: 15., z: 15. }.into()));
+
+        let x = Vec3 { x: 555., y: 0., z: 0. };
+        let y = Vec3 { x: 0., y: 555., z: 0. };
+        let z = Vec3 { x: 0., y: 0., z: 555. };
+
+        // every wall faces the inside of the box
+        let mut s = Shapes::new();
+        s.add(Mesh::quad(x, z, y, green));
+        s.add(Mesh::quad(Vec3::ZERO, y, z, red));
+        s.add(Mesh::quad(Vec3::ZERO, z, x, white.clone()));
+        s.add(Mesh::quad(y, x, z, white.clone()));
+        s.add(Mesh::quad(z, y, x, white));
+        let ceiling = Mesh::quad(
+            Vec3 { x: 213., y: 554., z: 227. },
+            Vec3 { x: 130., y: 0., z: 0. },
+            Vec3 { x: 0., y: 0., z: 105. },
+            light,
+        );
+        let mut l = Lights::new();
+        l.add(
+            MeshLight::new(&ceiling, &Transform::IDENTITY).expect("the ceiling light is emissive"),
+        );
+        s.add(ceiling);
+        (s, l)
+    }
+
+    fn cornell_scene(shapes: Shapes<'static>, lights: Lights<'static>) -> Scene {
+        #[rustfmt::skip]
+        let camera = CameraSettings {
+            look_from: Vec3 { x: 278., y: 278., z: -800. },
+            look_at:   Vec3 { x: 278., y: 278., z:    0. },
+            view_up:   Vec3 { x:   0., y:   1., z:    0. },
+            v_fov_deg: 40.,
+            aperture: 0.,
+            focus_dist: 10.,
+            shutter_open: 0.,
+            shutter_close: 0.,
+        };
+
+        Scene { camera, shapes, lights, background: Background::Solid(Vec3::ZERO) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAMERA: &str =
+        "[camera]\nlook_from = [0.0, 0.0, 5.0]\nlook_at = [0.0, 0.0, 0.0]\nfov = 40.0\n";
+
+    fn parse(source: &str) -> Result<Scene, SceneError> {
+        Scene::parse(source, Path::new("scenes/test.toml"), &mut Rng::with_seed(0))
+    }
+
+    fn parse_error(source: &str) -> (usize, usize, String) {
+        match parse(source) {
+            Err(SceneError::Parse { line, column, message, .. }) => (line, column, message),
+            other => panic!("expected a parse error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn loads_a_scene() {
+        let source = format!(
+            "{CAMERA}[materials]\nred = {{ type = \"lambertian\", albedo = [0.8, 0.1, 0.1] }}\n\
+             lamp = {{ type = \"diffuse_light\", radiance = [4.0, 4.0, 4.0] }}\n\
+             [[spheres]]\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\nmaterial = \"red\"\n\
+             [[spheres]]\ncenter = [0.0, 3.0, 0.0]\nradius = 0.5\nmaterial = \"lamp\"\n\
+             [[quads]]\norigin = [-1.0, -1.0, -1.0]\nu = [2.0, 0.0, 0.0]\nv = [0.0, 2.0, 0.0]\n\
+             material = \"red\"\n\
+             [[quads]]\norigin = [-1.0, 4.0, -1.0]\nu = [2.0, 0.0, 0.0]\nv = [0.0, 0.0, 2.0]\n\
+             material = \"lamp\"\n"
+        );
+        let scene = parse(&source).unwrap();
+        assert_eq!(scene.shapes.size(), 4);
+        // the emissive sphere and quad
+        assert_eq!(scene.lights.size(), 2);
+    }
+
+    #[test]
+    fn reports_syntax_errors_with_their_position() {
+        let (line, column, _) = parse_error(&format!("{CAMERA}[materials\n"));
+        assert_eq!((line, column), (5, 11));
+    }
+
+    #[test]
+    fn reports_unknown_fields_with_their_position() {
+        let (line, _, message) = parse_error(&format!("{CAMERA}aperture_size = 0.1\n"));
+        assert_eq!(line, 5);
+        assert!(message.contains("aperture_size"), "{message}");
+    }
+
+    #[test]
+    fn reports_unknown_materials_where_they_are_used() {
+        let source = format!(
+            "{CAMERA}\n[[spheres]]\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\nmaterial = \"chrome\"\n"
+        );
+        let (line, column, message) = parse_error(&source);
+        assert_eq!((line, column), (9, 12));
+        assert_eq!(message, "unknown material 'chrome'");
+    }
+
+    #[test]
+    fn displays_errors_as_file_line_and_column() {
+        let err = parse(&format!("{CAMERA}fov = 50.0\n")).unwrap_err();
+        let message = err.to_string();
+        assert!(message.starts_with("scenes/test.toml:5:1: "), "{message}");
+    }
+}
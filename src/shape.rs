@@ -1,66 +1,205 @@
-use std::fmt::Debug;
+use std::{f32::consts::PI, fmt::Debug, sync::Arc};
 
-use crate::{material::Material, ray::Ray, vec::Vec3};
+use fastrand::Rng;
+
+use crate::{aabb::Aabb, material::Material, ray::Ray, vec::Vec3};
 
 #[derive(Clone, Copy, Debug)]
-pub struct HitRecord {
+pub struct HitRecord<'a> {
     pub distance: f32,
     pub point: Vec3,
+    // unit normal used for shading, e.g. interpolated from the vertex normals
     pub normal: Vec3,
-    pub material: Material,
+    // unit normal of the actual surface; both normals face the ray
+    pub geometric_normal: Vec3,
+    // whether the ray hit the outside of the surface
+    pub front_face: bool,
+    pub u: f32,
+    pub v: f32,
+    // partial derivatives of the point with respect to u and v
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+    pub material: &'a dyn Material,
 }
 
-pub trait Shape: Debug + Sync {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+impl HitRecord<'_> {
+    // Turns the normals, which shapes compute pointing outwards, towards the ray
+    // and records which side was hit, so that materials shade both sides alike.
+    #[inline]
+    pub fn facing(self, ray: &Ray) -> Self {
+        let front_face = ray.direction.dot(self.geometric_normal) < 0.;
+        if front_face {
+            HitRecord { front_face, ..self }
+        } else {
+            HitRecord {
+                normal: -self.normal,
+                geometric_normal: -self.geometric_normal,
+                front_face,
+                ..self
+            }
+        }
+    }
 }
 
-#[derive(Clone, Copy, Debug)]
+pub trait Shape: Debug + Send + Sync {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>>;
+
+    // Like `hit`, with the path's random stream for shapes that are sampled
+    // rather than intersected, like media. Shapes made of others pass it on.
+    fn sample_hit(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        _rng: &mut Rng,
+    ) -> Option<HitRecord<'_>> {
+        self.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb>;
+}
+
+// Shared shapes, e.g. a mesh placed several times with `Transformed`.
+impl<S: Shape + ?Sized> Shape for Arc<S> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        (**self).hit(ray, t_min, t_max)
+    }
+
+    fn sample_hit(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        rng: &mut Rng,
+    ) -> Option<HitRecord<'_>> {
+        (**self).sample_hit(ray, t_min, t_max, rng)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> { (**self).bounding_box() }
+}
+
+#[derive(Clone, Debug)]
 pub struct Sphere {
     pub center: Vec3,
     pub radius: f32,
-    pub material: Material,
+    pub material: Arc<dyn Material>,
+}
+
+// Intersects a sphere, shared by the static and the moving one and by spheres
+// sampled as lights.
+pub(crate) fn hit_sphere<'a>(
+    center: Vec3,
+    radius: f32,
+    material: &'a dyn Material,
+    ray: &Ray,
+    t_min: f32,
+    t_max: f32,
+) -> Option<HitRecord<'a>> {
+    let oc = ray.origin - center;
+    let a = ray.direction.dot(ray.direction);
+    let b = oc.dot(ray.direction);
+    let c = oc.dot(oc) - radius * radius;
+
+    let discriminant = b * b - a * c;
+    if discriminant > 0. {
+        let t = (-b - discriminant.sqrt()) / a;
+        if t > t_min && t < t_max {
+            return Some(sphere_record(center, radius, material, ray, t));
+        }
+
+        let t = (-b + discriminant.sqrt()) / a;
+        if t > t_min && t < t_max {
+            return Some(sphere_record(center, radius, material, ray, t));
+        }
+    }
+
+    None
+}
+
+fn sphere_record<'a>(
+    center: Vec3,
+    radius: f32,
+    material: &'a dyn Material,
+    ray: &Ray,
+    t: f32,
+) -> HitRecord<'a> {
+    let point = ray.point_at(t);
+    let n = (point - center) / radius;
+    // longitude around the y axis starting at -x, and latitude from -y to +y
+    let u = (-n.z).atan2(n.x) / (2. * PI) + 0.5;
+    let v = (-n.y).clamp(-1., 1.).acos() / PI;
+
+    let dpdu = Vec3 { x: n.z, y: 0., z: -n.x } * (2. * PI * radius);
+    let sin_theta = (n.x * n.x + n.z * n.z).sqrt();
+    let dpdv = if sin_theta > 0. {
+        Vec3 { x: -n.y * n.x / sin_theta, y: sin_theta, z: -n.y * n.z / sin_theta }
+    } else {
+        // u is degenerate at the poles, pick the meridian at u = 0.5
+        Vec3 { x: -n.y, y: 0., z: 0. }
+    } * (PI * radius);
+
+    HitRecord {
+        distance: t,
+        point,
+        normal: n,
+        geometric_normal: n,
+        front_face: true,
+        u,
+        v,
+        dpdu,
+        dpdv,
+        material,
+    }
+    .facing(ray)
 }
 
 impl Shape for Sphere {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let oc = ray.origin - self.center;
-        let a = ray.direction.dot(ray.direction);
-        let b = oc.dot(ray.direction);
-        let c = oc.dot(oc) - self.radius * self.radius;
-
-        let discriminant = b * b - a * c;
-        if discriminant > 0. {
-            let t = (-b - discriminant.sqrt()) / a;
-            if t > t_min && t < t_max {
-                let point = ray.point_at(t);
-                let rec = HitRecord {
-                    distance: t,
-                    point,
-                    normal: (point - self.center) / self.radius,
-                    material: self.material,
-                };
-                return Some(rec);
-            }
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        hit_sphere(self.center, self.radius, &*self.material, ray, t_min, t_max)
+    }
 
-            let t = (-b + discriminant.sqrt()) / a;
-            if t > t_min && t < t_max {
-                let point = ray.point_at(t);
-                let rec = HitRecord {
-                    distance: t,
-                    point,
-                    normal: (point - self.center) / self.radius,
-                    material: self.material,
-                };
-                return Some(rec);
-            }
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Vec3 { x: self.radius, y: self.radius, z: self.radius };
+        Some(Aabb::new(self.center - r, self.center + r))
+    }
+}
+
+// A sphere moving in a straight line from `center0` at `time0` to `center1` at
+// `time1`, resting at either end outside of that interval.
+#[derive(Clone, Debug)]
+pub struct MovingSphere {
+    pub center0: Vec3,
+    pub center1: Vec3,
+    pub time0: f32,
+    pub time1: f32,
+    pub radius: f32,
+    pub material: Arc<dyn Material>,
+}
+
+impl MovingSphere {
+    pub fn center(&self, time: f32) -> Vec3 {
+        if self.time1 <= self.time0 {
+            return self.center0;
         }
+        let t = ((time - self.time0) / (self.time1 - self.time0)).clamp(0., 1.);
+        self.center0 + (self.center1 - self.center0) * t
+    }
+}
+
+impl Shape for MovingSphere {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        hit_sphere(self.center(ray.time), self.radius, &*self.material, ray, t_min, t_max)
+    }
 
-        None
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Vec3 { x: self.radius, y: self.radius, z: self.radius };
+        let start = Aabb::new(self.center0 - r, self.center0 + r);
+        Some(start.union(Aabb::new(self.center1 - r, self.center1 + r)))
     }
 }
 
 #[derive(Debug, Default)]
-pub struct Shapes<'a>(Vec<Box<dyn Shape + 'a>>);
+pub struct Shapes<'a>(pub(crate) Vec<Box<dyn Shape + 'a>>);
 
 impl<'a> Shapes<'a> {
     pub fn new() -> Shapes<'a> {
@@ -74,9 +213,39 @@ impl<'a> Shapes<'a> {
 }
 
 impl<'a> Shape for Shapes<'a> {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         self.0
             .iter()
             .fold(None, |acc, s| s.hit(r, t_min, acc.map(|r| r.distance).unwrap_or(t_max)).or(acc))
     }
+
+    fn sample_hit(&self, r: &Ray, t_min: f32, t_max: f32, rng: &mut Rng) -> Option<HitRecord<'_>> {
+        self.0.iter().fold(None, |acc, s| {
+            s.sample_hit(r, t_min, acc.map(|r| r.distance).unwrap_or(t_max), rng).or(acc)
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.0.iter().try_fold(Aabb::EMPTY, |acc, s| s.bounding_box().map(|b| acc.union(b)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn sphere_normals_face_the_ray() {
+        let sphere =
+            Sphere { center: Vec3::ZERO, radius: 1., material: Arc::new(Lambertian(0.5.into())) };
+        let direction = Vec3 { x: 0., y: 0., z: -1. };
+        for (origin, front_face) in [(3., true), (0., false)] {
+            let ray = Ray { origin: Vec3 { x: 0., y: 0., z: origin }, direction, time: 0. };
+            let rec = sphere.hit(&ray, 0.001, f32::MAX).unwrap();
+            assert_eq!(rec.front_face, front_face);
+            assert!(rec.normal.dot(direction) < 0.);
+            assert!(rec.geometric_normal.dot(direction) < 0.);
+        }
+    }
 }
@@ -0,0 +1,172 @@
+use std::f32::consts::{FRAC_PI_2, PI};
+
+use fastrand::Rng;
+
+use crate::{
+    light::{Light, LightSample},
+    ray::Ray,
+    sampling::{self, Onb},
+    shape::HitRecord,
+    vec::Vec3,
+};
+
+// Luminances in kcd/m^2 and illuminances in klux are multiplied by this to give
+// radiance and irradiance, so that a white surface under a clear noon sky comes
+// out at about 1.
+const RADIANCE_SCALE: f32 = 0.025;
+
+// Illuminance from the sun above the atmosphere, in klux.
+const SUN_ILLUMINANCE: f32 = 128.;
+
+// 1 - cos of the sun's angular radius of 0.265 degrees.
+const SUN_CONE_HEIGHT: f32 = 1.07e-5;
+
+// Wavelengths in micrometers the sun's transmittance is computed at, standing
+// in for the red, green and blue channels.
+const WAVELENGTHS: [f32; 3] = [0.65, 0.55, 0.45];
+
+// Clear daylight after "A Practical Analytic Model for Daylight" by Preetham,
+// Shirley and Smits: the sky's radiance from a handful of fitted functions of
+// the sun's position and the haziness of the air, plus the sun's disk, which is
+// sampled as a light.
+#[derive(Clone, Debug)]
+pub struct Sky {
+    // unit vector towards the sun
+    sun: Vec3,
+    // Y, x and y at the zenith, then the Perez coefficients A to E for each
+    zenith: [f32; 3],
+    perez: [[f32; 5]; 3],
+    // Perez function at the zenith, which the sky is normalized by
+    zenith_perez: [f32; 3],
+    sun_radiance: Vec3,
+}
+
+impl Sky {
+    // The sun's elevation above the horizon and its azimuth around the y axis,
+    // 0 towards -z and 90 towards +x, are in degrees. Turbidity is the haziness
+    // of the air, from 2 (very clear) to 10 (hazy).
+    pub fn new(elevation: f32, azimuth: f32, turbidity: f32) -> Sky {
+        let t = turbidity.clamp(1.7, 10.);
+        let (elevation, azimuth) = (elevation.clamp(-90., 90.).to_radians(), azimuth.to_radians());
+        let sun = Vec3 {
+            x: elevation.cos() * azimuth.sin(),
+            y: elevation.sin(),
+            z: -elevation.cos() * azimuth.cos(),
+        };
+        // the fits don't hold below the horizon, keep the sky at sunset
+        let theta_s = (FRAC_PI_2 - elevation).min(FRAC_PI_2);
+
+        let chi = (4. / 9. - t / 120.) * (PI - 2. * theta_s);
+        let luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let chromaticity = |c: [[f32; 4]; 3]| {
+            let poly = |k: [f32; 4]| {
+                k[0] * theta_s.powi(3) + k[1] * theta_s.powi(2) + k[2] * theta_s + k[3]
+            };
+            t * t * poly(c[0]) + t * poly(c[1]) + poly(c[2])
+        };
+        #[rustfmt::skip]
+        let x = chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        #[rustfmt::skip]
+        let y = chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+
+        #[rustfmt::skip]
+        let perez = [
+            [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+            [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+            [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529],
+        ];
+        let zenith_perez = perez.map(|c| perez_function(c, 1., theta_s.cos()));
+
+        // Rayleigh and aerosol extinction along the sun's path through the air,
+        // with the relative air mass of Kasten and Young
+        let sun_radiance = if elevation > 0. {
+            let zenith_deg = theta_s.to_degrees();
+            let air_mass = 1. / (theta_s.cos() + 0.15 * (93.885 - zenith_deg).powf(-1.253));
+            let beta = 0.04608 * t - 0.04586;
+            let transmittance = WAVELENGTHS.map(|lambda| {
+                let rayleigh = 0.008735 * lambda.powf(-4.08);
+                let aerosol = beta * lambda.powf(-1.3);
+                (-air_mass * (rayleigh + aerosol)).exp()
+            });
+            Vec3::from(transmittance)
+                * (SUN_ILLUMINANCE * RADIANCE_SCALE * sampling::uniform_cone_pdf(SUN_CONE_HEIGHT))
+        } else {
+            Vec3::ZERO
+        };
+
+        Sky { sun, zenith: [luminance, x, y], perez, zenith_perez, sun_radiance }
+    }
+
+    // Radiance arriving from `direction`, including the sun.
+    pub fn radiance(&self, direction: Vec3) -> Vec3 {
+        let direction = direction.unit();
+        let cos_gamma = direction.dot(self.sun);
+        let sun = if 1. - cos_gamma < SUN_CONE_HEIGHT { self.sun_radiance } else { Vec3::ZERO };
+        self.sky_radiance(direction, cos_gamma) + sun
+    }
+
+    fn sky_radiance(&self, direction: Vec3, cos_gamma: f32) -> Vec3 {
+        // below the horizon the sky at the horizon carries on
+        let cos_theta = direction.y.max(0.01);
+        let [luminance, x, y] = std::array::from_fn(|i| {
+            self.zenith[i] * perez_function(self.perez[i], cos_theta, cos_gamma)
+                / self.zenith_perez[i]
+        });
+        xyy_to_rgb(luminance * RADIANCE_SCALE, x, y)
+    }
+}
+
+// Relative distribution of the sky's brightness by the angle from the zenith
+// and the angle from the sun.
+fn perez_function([a, b, c, d, e]: [f32; 5], cos_theta: f32, cos_gamma: f32) -> f32 {
+    let gamma = cos_gamma.clamp(-1., 1.).acos();
+    (1. + a * (b / cos_theta).exp()) * (1. + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+}
+
+// CIE xyY to linear sRGB.
+fn xyy_to_rgb(luminance: f32, x: f32, y: f32) -> Vec3 {
+    let big_x = x / y * luminance;
+    let big_z = (1. - x - y) / y * luminance;
+    Vec3 {
+        x: 3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z,
+        y: -0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z,
+        z: 0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z,
+    }
+    .max(Vec3::ZERO)
+}
+
+// Samples the sun's disk, the rest of the sky is only found by scattered rays.
+impl Light for Sky {
+    fn sample(&self, _origin: Vec3, rng: &mut Rng) -> Option<LightSample> {
+        if self.sun_radiance.max_component() <= 0. {
+            return None;
+        }
+        let direction = Onb::new(self.sun).local(sampling::uniform_cone(rng, SUN_CONE_HEIGHT));
+        Some(LightSample {
+            direction,
+            distance: f32::MAX,
+            radiance: self.radiance(direction),
+            pdf: sampling::uniform_cone_pdf(SUN_CONE_HEIGHT),
+            delta: false,
+        })
+    }
+
+    fn pdf(&self, _origin: Vec3, direction: Vec3) -> f32 {
+        let in_disk = 1. - direction.unit().dot(self.sun) < SUN_CONE_HEIGHT;
+        if in_disk && self.sun_radiance.max_component() > 0. {
+            sampling::uniform_cone_pdf(SUN_CONE_HEIGHT)
+        } else {
+            0.
+        }
+    }
+
+    fn is_hit_by(&self, _ray: &Ray, rec: Option<&HitRecord>) -> bool { rec.is_none() }
+}
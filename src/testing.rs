@@ -0,0 +1,28 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+// A scratch directory for tests that need real files, removed when dropped so
+// that nothing is left behind when an assert fails.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        let dir = std::env::temp_dir().join(format!("tachibana-{}-{name}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    pub fn path(&self) -> &Path { &self.0 }
+
+    pub fn write(&self, file: &str, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.0.join(file);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) { _ = fs::remove_dir_all(&self.0); }
+}
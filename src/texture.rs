@@ -0,0 +1,228 @@
+use std::{fmt, path::Path, str::FromStr, sync::Arc};
+
+use image::ImageResult;
+
+use crate::{noise::Noise, vec::Vec3};
+
+#[derive(Clone, Debug)]
+pub enum Texture {
+    Solid(Vec3),
+    // alternates between two textures in a 3D grid of cubes `1 / scale` wide
+    Checker {
+        even: Box<Texture>,
+        odd: Box<Texture>,
+        scale: f32,
+    },
+    Image(Arc<ImageTexture>),
+    // blends from `low` to `high` following a procedural pattern evaluated at
+    // the point scaled by `scale`
+    Noise {
+        noise: Arc<Noise>,
+        pattern: Pattern,
+        scale: f32,
+        // layers of detail of the turbulent patterns
+        octaves: u32,
+        low: Box<Texture>,
+        high: Box<Texture>,
+    },
+}
+
+impl Texture {
+    pub fn value(&self, u: f32, v: f32, p: Vec3) -> Vec3 {
+        match self {
+            Texture::Solid(color) => *color,
+            Texture::Checker { even, odd, scale } => {
+                let cell = (p.x * scale).floor() + (p.y * scale).floor() + (p.z * scale).floor();
+                if cell.rem_euclid(2.) == 0. { even.value(u, v, p) } else { odd.value(u, v, p) }
+            }
+            Texture::Image(image) => image.value(u, v),
+            Texture::Noise { noise, pattern, scale, octaves, low, high } => {
+                let t = pattern.value(noise, p * *scale, *octaves).clamp(0., 1.);
+                low.value(u, v, p) * (1. - t) + high.value(u, v, p) * t
+            }
+        }
+    }
+}
+
+impl From<Vec3> for Texture {
+    fn from(color: Vec3) -> Self { Texture::Solid(color) }
+}
+
+impl From<f32> for Texture {
+    fn from(x: f32) -> Self { Texture::Solid(Vec3 { x, y: x, z: x }) }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pattern {
+    Perlin,
+    Turbulence,
+    // veins along the z axis, distorted by turbulence
+    Marble,
+    // rings around the y axis
+    Wood,
+    // distance to the closest of randomly scattered points
+    Voronoi,
+}
+
+impl Pattern {
+    pub const ALL: [Pattern; 5] =
+        [Pattern::Perlin, Pattern::Turbulence, Pattern::Marble, Pattern::Wood, Pattern::Voronoi];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Pattern::Perlin => "perlin",
+            Pattern::Turbulence => "turbulence",
+            Pattern::Marble => "marble",
+            Pattern::Wood => "wood",
+            Pattern::Voronoi => "voronoi",
+        }
+    }
+
+    // Roughly in [0, 1].
+    fn value(self, noise: &Noise, p: Vec3, octaves: u32) -> f32 {
+        match self {
+            Pattern::Perlin => 0.5 * (1. + noise.perlin(p)),
+            Pattern::Turbulence => noise.turbulence(p, octaves),
+            Pattern::Marble => 0.5 * (1. + (p.z + 10. * noise.turbulence(p, octaves)).sin()),
+            Pattern::Wood => {
+                let radius = (p.x * p.x + p.z * p.z).sqrt();
+                (radius + 0.5 * noise.turbulence(p * 0.5, octaves)).fract()
+            }
+            Pattern::Voronoi => noise.voronoi(p).0,
+        }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Pattern::ALL.into_iter().find(|pattern| pattern.name() == s).ok_or_else(|| {
+            let names: Vec<_> = Pattern::ALL.iter().map(|pattern| pattern.name()).collect();
+            format!("unknown noise pattern '{s}', expected one of: {}", names.join(", "))
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Wrap {
+    Repeat,
+    Mirror,
+    Clamp,
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(Filter::Nearest),
+            "bilinear" => Ok(Filter::Bilinear),
+            _ => Err(format!("unknown filter '{s}', expected nearest or bilinear")),
+        }
+    }
+}
+
+impl FromStr for Wrap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "repeat" => Ok(Wrap::Repeat),
+            "mirror" => Ok(Wrap::Mirror),
+            "clamp" => Ok(Wrap::Clamp),
+            _ => Err(format!("unknown wrap mode '{s}', expected repeat, mirror or clamp")),
+        }
+    }
+}
+
+impl Wrap {
+    fn apply(self, i: i64, size: u32) -> u32 {
+        let size = i64::from(size);
+        let i = match self {
+            Wrap::Repeat => i.rem_euclid(size),
+            Wrap::Mirror => {
+                let i = i.rem_euclid(2 * size);
+                if i < size { i } else { 2 * size - 1 - i }
+            }
+            Wrap::Clamp => i.clamp(0, size - 1),
+        };
+        i as u32
+    }
+}
+
+// Texels are stored as linear RGB, in rows from top to bottom.
+pub struct ImageTexture {
+    width: u32,
+    height: u32,
+    texels: Vec<Vec3>,
+    filter: Filter,
+    wrap: Wrap,
+}
+
+impl fmt::Debug for ImageTexture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImageTexture")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("filter", &self.filter)
+            .field("wrap", &self.wrap)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ImageTexture {
+    // Loads an sRGB encoded image, e.g. a PNG, converting it to linear values.
+    pub fn load<P: AsRef<Path>>(path: P, filter: Filter, wrap: Wrap) -> ImageResult<ImageTexture> {
+        let image = image::open(path)?.into_rgb8();
+        let (width, height) = image.dimensions();
+        let texels = image
+            .pixels()
+            .map(|p| Vec3 { x: p[0] as f32, y: p[1] as f32, z: p[2] as f32 } / 255.)
+            .map(|c| c.map(srgb_to_linear))
+            .collect();
+        Ok(ImageTexture { width, height, texels, filter, wrap })
+    }
+
+    pub fn new(width: u32, height: u32, texels: Vec<Vec3>, filter: Filter, wrap: Wrap) -> Self {
+        assert_eq!(texels.len(), (width * height) as usize);
+        ImageTexture { width, height, texels, filter, wrap }
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Vec3 {
+        let x = self.wrap.apply(x, self.width);
+        let y = self.wrap.apply(y, self.height);
+        self.texels[(y * self.width + x) as usize]
+    }
+
+    // (0, 0) is the bottom left corner of the image, (1, 1) the top right one.
+    pub fn value(&self, u: f32, v: f32) -> Vec3 {
+        let x = u * self.width as f32;
+        let y = (1. - v) * self.height as f32;
+
+        match self.filter {
+            Filter::Nearest => self.texel(x.floor() as i64, y.floor() as i64),
+            Filter::Bilinear => {
+                // texel centers are at half-integer coordinates
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+
+                let top = self.texel(x0, y0) * (1. - fx) + self.texel(x0 + 1, y0) * fx;
+                let bottom = self.texel(x0, y0 + 1) * (1. - fx) + self.texel(x0 + 1, y0 + 1) * fx;
+                top * (1. - fy) + bottom * fy
+            }
+        }
+    }
+}
+
+fn srgb_to_linear(x: f32) -> f32 {
+    if x <= 0.04045 { x / 12.92 } else { ((x + 0.055) / 1.055).powf(2.4) }
+}
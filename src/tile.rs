@@ -0,0 +1,133 @@
+use std::{fmt, str::FromStr};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TileOrder {
+    Scanline,
+    Spiral,
+    Hilbert,
+}
+
+impl TileOrder {
+    pub const ALL: [TileOrder; 3] = [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TileOrder::Scanline => "scanline",
+            TileOrder::Spiral => "spiral",
+            TileOrder::Hilbert => "hilbert",
+        }
+    }
+}
+
+impl fmt::Display for TileOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { f.write_str(self.name()) }
+}
+
+impl FromStr for TileOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TileOrder::ALL.into_iter().find(|order| order.name() == s).ok_or_else(|| {
+            let names: Vec<_> = TileOrder::ALL.iter().map(|order| order.name()).collect();
+            format!("unknown tile order '{s}', expected one of: {}", names.join(", "))
+        })
+    }
+}
+
+// Splits the image into square tiles of `tile_size` pixels (smaller along the
+// right and bottom edges) and lists them in the order they should be rendered.
+pub fn tiles(width: u32, height: u32, tile_size: u32, order: TileOrder) -> Vec<Tile> {
+    let tile_size = tile_size.max(1);
+    let columns = width.div_ceil(tile_size);
+    let rows = height.div_ceil(tile_size);
+
+    let grid: Vec<(u32, u32)> = match order {
+        TileOrder::Scanline => (0..rows).flat_map(|r| (0..columns).map(move |c| (c, r))).collect(),
+        TileOrder::Spiral => spiral(columns, rows),
+        TileOrder::Hilbert => hilbert(columns, rows),
+    };
+
+    grid.into_iter()
+        .map(|(c, r)| {
+            let x = c * tile_size;
+            let y = r * tile_size;
+            Tile { x, y, width: tile_size.min(width - x), height: tile_size.min(height - y) }
+        })
+        .collect()
+}
+
+// Walks outwards from the center tile in a square spiral, skipping the steps
+// that fall outside of the grid.
+fn spiral(columns: u32, rows: u32) -> Vec<(u32, u32)> {
+    let total = (columns * rows) as usize;
+    let mut grid = Vec::with_capacity(total);
+
+    let (mut c, mut r) =
+        (i64::from(columns.saturating_sub(1) / 2), i64::from(rows.saturating_sub(1) / 2));
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut direction = 0;
+    let mut run = 1;
+
+    let visit = |c: i64, r: i64, grid: &mut Vec<(u32, u32)>| {
+        if (0..i64::from(columns)).contains(&c) && (0..i64::from(rows)).contains(&r) {
+            grid.push((c as u32, r as u32));
+        }
+    };
+
+    visit(c, r, &mut grid);
+    while grid.len() < total {
+        // every run length is used twice: right 1, down 1, left 2, up 2, right 3...
+        for _ in 0..2 {
+            let (dc, dr) = directions[direction];
+            for _ in 0..run {
+                c += dc;
+                r += dr;
+                visit(c, r, &mut grid);
+            }
+            direction = (direction + 1) % 4;
+        }
+        run += 1;
+    }
+
+    grid
+}
+
+// Follows a Hilbert curve over the smallest power-of-two square covering the
+// grid, skipping the cells that fall outside of it.
+fn hilbert(columns: u32, rows: u32) -> Vec<(u32, u32)> {
+    let n = columns.max(rows).max(1).next_power_of_two();
+    (0..u64::from(n) * u64::from(n))
+        .map(|d| hilbert_point(n, d))
+        .filter(|&(c, r)| c < columns && r < rows)
+        .collect()
+}
+
+fn hilbert_point(n: u32, d: u64) -> (u32, u32) {
+    let (mut x, mut y) = (0u64, 0u64);
+    let mut t = d;
+    let mut s = 1u64;
+    while s < u64::from(n) {
+        let rx = 1 & (t / 2);
+        let ry = 1 & (t ^ rx);
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+    (x as u32, y as u32)
+}
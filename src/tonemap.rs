@@ -0,0 +1,143 @@
+use std::{fmt, str::FromStr};
+
+use crate::vec::Vec3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operator {
+    Clamp,
+    Reinhard,
+    ExtendedReinhard,
+    Aces,
+    Agx,
+}
+
+impl Operator {
+    pub const ALL: [Operator; 5] = [
+        Operator::Clamp,
+        Operator::Reinhard,
+        Operator::ExtendedReinhard,
+        Operator::Aces,
+        Operator::Agx,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Operator::Clamp => "clamp",
+            Operator::Reinhard => "reinhard",
+            Operator::ExtendedReinhard => "extended-reinhard",
+            Operator::Aces => "aces",
+            Operator::Agx => "agx",
+        }
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { f.write_str(self.name()) }
+}
+
+impl FromStr for Operator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Operator::ALL.into_iter().find(|op| op.name() == s).ok_or_else(|| {
+            let names: Vec<_> = Operator::ALL.iter().map(|op| op.name()).collect();
+            format!("unknown tone mapping operator '{s}', expected one of: {}", names.join(", "))
+        })
+    }
+}
+
+// Maps linear scene radiance to linear display values in [0, 1], ready for the
+// sRGB transfer function and quantization.
+#[derive(Clone, Copy, Debug)]
+pub struct ToneMapping {
+    pub operator: Operator,
+    // exposure adjustment in stops, applied before the operator
+    pub exposure: f32,
+    // the smallest radiance mapped to pure white by the extended Reinhard operator
+    pub white_point: f32,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        ToneMapping { operator: Operator::Clamp, exposure: 0., white_point: 4. }
+    }
+}
+
+impl ToneMapping {
+    pub fn apply(&self, c: Vec3) -> Vec3 {
+        let c = (c * self.exposure.exp2()).map(|x| if x.is_nan() { 0. } else { x.max(0.) });
+
+        let mapped = match self.operator {
+            Operator::Clamp => c,
+            Operator::Reinhard => c.map(|x| x / (1. + x)),
+            Operator::ExtendedReinhard => {
+                let white_squared = self.white_point * self.white_point;
+                c.map(|x| x * (1. + x / white_squared) / (1. + x))
+            }
+            Operator::Aces => aces(c),
+            Operator::Agx => agx(c),
+        };
+
+        mapped.map(|x| x.clamp(0., 1.))
+    }
+}
+
+fn mat_mul(m: &[[f32; 3]; 3], v: Vec3) -> Vec3 {
+    Vec3 {
+        x: m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+        y: m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+        z: m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+    }
+}
+
+// Stephen Hill's fit of the ACES reference rendering and sRGB output
+// transforms.
+fn aces(c: Vec3) -> Vec3 {
+    #[rustfmt::skip]
+    const INPUT: [[f32; 3]; 3] = [
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777],
+    ];
+    #[rustfmt::skip]
+    const OUTPUT: [[f32; 3]; 3] = [
+        [ 1.60475, -0.53108, -0.07367],
+        [-0.10208,  1.10813, -0.00605],
+        [-0.00327, -0.07276,  1.07602],
+    ];
+
+    let v = mat_mul(&INPUT, c);
+    let v =
+        v.map(|x| (x * (x + 0.0245786) - 0.000090537) / (x * (0.983729 * x + 0.432951) + 0.238081));
+    mat_mul(&OUTPUT, v)
+}
+
+// The AgX base transform with a polynomial approximation of its sigmoid, after
+// Benjamin Wrensch's minimal implementation.
+fn agx(c: Vec3) -> Vec3 {
+    #[rustfmt::skip]
+    const INSET: [[f32; 3]; 3] = [
+        [0.8424791, 0.0784336, 0.0792237],
+        [0.0423282, 0.8784686, 0.0791661],
+        [0.0423757, 0.0784336, 0.879143 ],
+    ];
+    #[rustfmt::skip]
+    const OUTSET: [[f32; 3]; 3] = [
+        [ 1.196879,  -0.0980209, -0.0990297],
+        [-0.0528969,  1.1519031, -0.0989612],
+        [-0.0529716, -0.0980435,  1.1510737],
+    ];
+    const MIN_EV: f32 = -12.47393;
+    const MAX_EV: f32 = 4.026069;
+
+    let v = mat_mul(&INSET, c);
+    let v = v.map(|x| (x.max(1e-10).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV));
+    let v = v.map(|x| {
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232
+    });
+    // the sigmoid produces display-encoded values, linearize them again
+    mat_mul(&OUTSET, v).map(|x| x.max(0.).powf(2.2))
+}
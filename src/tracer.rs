@@ -1,61 +1,230 @@
+use std::sync::Arc;
+
 use fastrand::Rng;
 
 use crate::{
-    color::Color,
+    bvh::Bvh,
+    environment::EnvironmentMap,
+    light::Lights,
     ray::{Camera, Ray},
-    shape::{Shape, Shapes},
+    render::RenderSettings,
+    shape::{HitRecord, Shape},
+    sky::Sky,
     vec::Vec3,
 };
 
+#[derive(Clone, Debug)]
+pub enum Background {
+    // also sampled as a light, which needs to be in the scene's lights
+    Environment(Arc<EnvironmentMap>),
+    // blends vertically between `bottom` and `top` based on the ray direction
+    Gradient { bottom: Vec3, top: Vec3 },
+    // daylight with the sun, which like an environment map is also a light
+    Sky(Arc<Sky>),
+    Solid(Vec3),
+}
+
+impl Background {
+    pub const SKY: Background =
+        Background::Gradient { bottom: Vec3::ONE, top: Vec3 { x: 0.5, y: 0.7, z: 1. } };
+
+    fn color(&self, ray: &Ray) -> Vec3 {
+        match *self {
+            Background::Environment(ref map) => map.radiance(ray.direction),
+            Background::Gradient { bottom, top } => {
+                let unit_direction = ray.direction.unit();
+                let t = (unit_direction.y + 1.) * 0.5;
+                bottom * (1. - t) + top * t
+            }
+            Background::Sky(ref sky) => sky.radiance(ray.direction),
+            Background::Solid(color) => color,
+        }
+    }
+}
+
 pub struct Tracer<'a> {
     camera: &'a Camera,
-    shapes: &'a Shapes<'a>,
+    world: &'a Bvh<'a>,
+    lights: &'a Lights<'a>,
+    background: &'a Background,
     width: u32,
     height: u32,
     max_bounces: u32,
+    rr_depth: u32,
 }
 
 impl<'a> Tracer<'a> {
     pub fn new(
         camera: &'a Camera,
-        shapes: &'a Shapes,
-        width: u32,
-        height: u32,
-        max_bounces: u32,
+        world: &'a Bvh,
+        lights: &'a Lights,
+        settings: &'a RenderSettings,
     ) -> Tracer<'a> {
-        Tracer { camera, shapes, width, height, max_bounces }
+        Tracer {
+            camera,
+            world,
+            lights,
+            background: &settings.background,
+            width: settings.width,
+            height: settings.height,
+            max_bounces: settings.max_bounces,
+            rr_depth: settings.rr_depth,
+        }
     }
 
-    fn color_vec(ray: &Ray, world: &Shapes, max_depth: u32, depth: u32, rng: &mut Rng) -> Vec3 {
-        if let Some(rec) = world.hit(ray, 0.001, f32::MAX) {
-            if let Some((attenuation, scattered)) = rec.material.scatter(ray, &rec, rng) {
-                if depth < max_depth {
-                    return attenuation
-                        * Self::color_vec(&scattered, world, max_depth, depth + 1, rng);
+    // Follows one path from the camera, returning the radiance it carries and
+    // the number of rays traced along it. Lights are both sampled directly and
+    // found by the scattered rays, and the two estimates are combined with the
+    // power heuristic. After `rr_depth` bounces, paths are randomly terminated
+    // with a probability based on their throughput, and the survivors are
+    // weighted up to keep the estimate unbiased.
+    fn trace_path(&self, mut ray: Ray, rng: &mut Rng) -> (Vec3, u32) {
+        let mut radiance = Vec3::ZERO;
+        let mut throughput = Vec3::ONE;
+        // pdf of the scattered ray when lights were also sampled directly at its
+        // origin, None at the camera and after specular bounces
+        let mut scatter_pdf = None;
+        let mut depth = 0;
+        let mut rays = 0;
+
+        loop {
+            rays += 1;
+            let hit = self.world.sample_hit(&ray, 0.001, f32::MAX, rng);
+            let emitted = match &hit {
+                Some(rec) => rec.material.emitted(rec),
+                None => self.background.color(&ray),
+            };
+            if emitted.max_component() > 0. {
+                let weight = match scatter_pdf {
+                    Some(pdf) => power_heuristic(pdf, self.lights.pdf(&ray, hit.as_ref())),
+                    None => 1.,
+                };
+                radiance = radiance + throughput * emitted * weight;
+            }
+            let Some(rec) = hit else {
+                break;
+            };
+            let Some(sample) = rec.material.scatter(&ray, &rec, rng) else {
+                break;
+            };
+            if depth >= self.max_bounces {
+                break;
+            }
+
+            let direct = self.sample_lights(&ray, &rec, rng);
+            if let Some(direct) = direct {
+                radiance = radiance + throughput * direct;
+            }
+            scatter_pdf = direct.and(sample.pdf);
+            throughput = throughput * sample.weight();
+            ray = sample.ray;
+            depth += 1;
+
+            if depth >= self.rr_depth {
+                let survival = throughput.max_component().min(1.);
+                if rng.f32() >= survival {
+                    break;
                 }
+                throughput = throughput / survival;
             }
-            Vec3::ZERO
-        } else {
-            let unit_direction = ray.direction.unit();
-            let t = (unit_direction.y + 1.) * 0.5;
-            let color = Vec3 { x: 0.5, y: 0.7, z: 1. };
-            Vec3::ONE * (1. - t) + color * t
         }
+
+        (radiance, rays)
     }
 
-    fn gamma_linear_to_srgb(x: f32) -> f32 {
-        if x <= 0.0031308 { x * 12.92 } else { 1.055 * x.powf(1.0 / 2.4) - 0.055 }
+    // Next-event estimation: picks a light and traces a shadow ray towards it,
+    // weighted against finding the light by scattering. Returns None if the
+    // material can't be evaluated for arbitrary directions or there are no
+    // lights to sample.
+    fn sample_lights(&self, ray: &Ray, rec: &HitRecord, rng: &mut Rng) -> Option<Vec3> {
+        if self.lights.is_empty() || rec.material.is_specular(rec) {
+            return None;
+        }
+
+        let sample = match self.lights.sample(rec.point, rng) {
+            Some(sample) if sample.pdf > 0. => sample,
+            _ => return Some(Vec3::ZERO),
+        };
+        let f = rec.material.eval(ray, rec, sample.direction);
+        if f.max_component() <= 0. {
+            return Some(Vec3::ZERO);
+        }
+
+        let shadow_ray = Ray { origin: rec.point, direction: sample.direction, time: ray.time };
+        if self.world.sample_hit(&shadow_ray, 0.001, sample.distance * (1. - 1e-4), rng).is_some() {
+            return Some(Vec3::ZERO);
+        }
+
+        let weight = if sample.delta {
+            1.
+        } else {
+            power_heuristic(sample.pdf, rec.material.pdf(ray, rec, sample.direction))
+        };
+        Some(f * sample.radiance * (weight / sample.pdf))
     }
 
-    pub fn trace_pixel(&self, x: u32, y: u32, rays_per_pixel: u32, rng: &mut Rng) -> Color {
-        let c_vec = (0..rays_per_pixel).fold(Vec3::ZERO, |acc, _| {
+    // Returns the average linear radiance arriving through the pixel and the
+    // total number of rays traced for it.
+    pub fn trace_pixel(&self, x: u32, y: u32, rays_per_pixel: u32, rng: &mut Rng) -> (Vec3, u32) {
+        let (sum, rays) = (0..rays_per_pixel).fold((Vec3::ZERO, 0), |(acc, rays), _| {
             let u = (x as f32 + rng.f32()) / self.width as f32;
             let v = (y as f32 + rng.f32()) / self.height as f32;
             let ray = self.camera.ray(u, v, rng);
-            let c = Self::color_vec(&ray, self.shapes, self.max_bounces, 0, rng);
-            acc + c
-        }) / rays_per_pixel as f32;
+            let (c, n) = self.trace_path(ray, rng);
+            (acc + c, rays + n)
+        });
+        (sum / rays_per_pixel as f32, rays)
+    }
+}
+
+// Weight of a sample taken with density `a` when the same direction could also
+// have been sampled with density `b`.
+#[inline]
+fn power_heuristic(a: f32, b: f32) -> f32 {
+    // from the ratio, as squaring tiny or huge densities under- or overflows
+    if a.is_infinite() {
+        return 1.;
+    }
+    let ratio = b / a;
+    1. / (1. + ratio * ratio)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pixel_rng, scene::Scene};
 
-        c_vec.map(Self::gamma_linear_to_srgb).into()
+    // Mean radiance over a small image of the smoky Cornell box.
+    fn mean_radiance(rr_depth: u32) -> Vec3 {
+        let scene = Scene::cornell_smoke();
+        let settings = RenderSettings {
+            width: 16,
+            height: 16,
+            background: scene.background,
+            rr_depth,
+            ..RenderSettings::default()
+        };
+        let camera = scene.camera.camera(1.);
+        let world = Bvh::new(scene.shapes);
+        let tracer = Tracer::new(&camera, &world, &scene.lights, &settings);
+
+        let rays_per_pixel = 128;
+        let mut sum = Vec3::ZERO;
+        for y in 0..settings.height {
+            for x in 0..settings.width {
+                let mut rng = pixel_rng(7, x, y);
+                sum = sum + tracer.trace_pixel(x, y, rays_per_pixel, &mut rng).0;
+            }
+        }
+        sum / (settings.width * settings.height) as f32
+    }
+
+    #[test]
+    fn russian_roulette_keeps_the_mean_radiance() {
+        let full = mean_radiance(u32::MAX);
+        let roulette = mean_radiance(1);
+        for (a, b) in [(full.x, roulette.x), (full.y, roulette.y), (full.z, roulette.z)] {
+            assert!((a - b).abs() < 0.05 * a, "{full:?} != {roulette:?}");
+        }
     }
 }
@@ -0,0 +1,259 @@
+use std::ops::Mul;
+
+use fastrand::Rng;
+
+use crate::{
+    aabb::Aabb,
+    ray::Ray,
+    shape::{HitRecord, Shape},
+    vec::Vec3,
+};
+
+// Row-major 4x4 matrix acting on column vectors.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat4(pub [[f32; 4]; 4]);
+
+impl Mat4 {
+    #[rustfmt::skip]
+    pub const IDENTITY: Mat4 = Mat4([
+        [1., 0., 0., 0.],
+        [0., 1., 0., 0.],
+        [0., 0., 1., 0.],
+        [0., 0., 0., 1.],
+    ]);
+
+    pub fn transpose(&self) -> Mat4 {
+        let m = &self.0;
+        Mat4(std::array::from_fn(|i| std::array::from_fn(|j| m[j][i])))
+    }
+
+    // Gauss-Jordan elimination with partial pivoting, None if the matrix is
+    // singular.
+    pub fn inverse(&self) -> Option<Mat4> {
+        let mut a = self.0.map(|row| row.map(f64::from));
+        let mut inv = Mat4::IDENTITY.0.map(|row| row.map(f64::from));
+
+        for col in 0..4 {
+            let pivot = (col..4).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+            if a[pivot][col] == 0. {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = 1. / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+            for row in (0..4).filter(|&row| row != col) {
+                let factor = a[row][col];
+                for j in 0..4 {
+                    a[row][j] -= factor * a[col][j];
+                    inv[row][j] -= factor * inv[col][j];
+                }
+            }
+        }
+
+        Some(Mat4(inv.map(|row| row.map(|x| x as f32))))
+    }
+
+    #[inline]
+    pub fn point(&self, p: Vec3) -> Vec3 {
+        let m = &self.0;
+        let v = Vec3 {
+            x: m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3],
+            y: m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3],
+            z: m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3],
+        };
+        let w = m[3][0] * p.x + m[3][1] * p.y + m[3][2] * p.z + m[3][3];
+        if w == 1. { v } else { v / w }
+    }
+
+    #[inline]
+    pub fn vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.0;
+        Vec3 {
+            x: m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            y: m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            z: m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        }
+    }
+}
+
+impl Mul<Mat4> for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, b: Mat4) -> Self::Output {
+        let (a, b) = (&self.0, &b.0);
+        Mat4(std::array::from_fn(|i| {
+            std::array::from_fn(|j| (0..4).map(|k| a[i][k] * b[k][j]).sum())
+        }))
+    }
+}
+
+// An invertible affine transform, keeping the inverse around to move rays into
+// object space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    m: Mat4,
+    inv: Mat4,
+}
+
+impl Default for Transform {
+    fn default() -> Self { Transform::IDENTITY }
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform { m: Mat4::IDENTITY, inv: Mat4::IDENTITY };
+
+    pub fn new(m: Mat4) -> Option<Transform> { m.inverse().map(|inv| Transform { m, inv }) }
+
+    pub fn matrix(&self) -> &Mat4 { &self.m }
+
+    pub fn inverse(&self) -> Transform { Transform { m: self.inv, inv: self.m } }
+
+    #[rustfmt::skip]
+    pub fn translate(t: Vec3) -> Transform {
+        let m = Mat4([
+            [1., 0., 0., t.x],
+            [0., 1., 0., t.y],
+            [0., 0., 1., t.z],
+            [0., 0., 0., 1.],
+        ]);
+        let inv = Mat4([
+            [1., 0., 0., -t.x],
+            [0., 1., 0., -t.y],
+            [0., 0., 1., -t.z],
+            [0., 0., 0., 1.],
+        ]);
+        Transform { m, inv }
+    }
+
+    // Panics if any of the factors is 0.
+    #[rustfmt::skip]
+    pub fn scale(s: Vec3) -> Transform {
+        assert!(s.x != 0. && s.y != 0. && s.z != 0., "scale by 0");
+        let m = Mat4([
+            [s.x, 0.,  0.,  0.],
+            [0.,  s.y, 0.,  0.],
+            [0.,  0.,  s.z, 0.],
+            [0.,  0.,  0.,  1.],
+        ]);
+        let inv = Mat4([
+            [1. / s.x, 0.,       0.,       0.],
+            [0.,       1. / s.y, 0.,       0.],
+            [0.,       0.,       1. / s.z, 0.],
+            [0.,       0.,       0.,       1.],
+        ]);
+        Transform { m, inv }
+    }
+
+    // Counterclockwise rotation by `degrees` around `axis`, looking down the
+    // axis towards the origin.
+    pub fn rotate(axis: Vec3, degrees: f32) -> Transform {
+        let a = axis.unit();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let t = 1. - cos;
+        #[rustfmt::skip]
+        let m = Mat4([
+            [t * a.x * a.x + cos,       t * a.x * a.y - sin * a.z, t * a.x * a.z + sin * a.y, 0.],
+            [t * a.x * a.y + sin * a.z, t * a.y * a.y + cos,       t * a.y * a.z - sin * a.x, 0.],
+            [t * a.x * a.z - sin * a.y, t * a.y * a.z + sin * a.x, t * a.z * a.z + cos,       0.],
+            [0.,                        0.,                        0.,                        1.],
+        ]);
+        // rotations are orthogonal
+        Transform { m, inv: m.transpose() }
+    }
+
+    #[inline]
+    pub fn point(&self, p: Vec3) -> Vec3 { self.m.point(p) }
+
+    #[inline]
+    pub fn vector(&self, v: Vec3) -> Vec3 { self.m.vector(v) }
+
+    // Normals transform by the inverse transpose to stay perpendicular to the
+    // surface; the result is a unit vector.
+    #[inline]
+    pub fn normal(&self, n: Vec3) -> Vec3 {
+        let m = &self.inv.0;
+        Vec3 {
+            x: m[0][0] * n.x + m[1][0] * n.y + m[2][0] * n.z,
+            y: m[0][1] * n.x + m[1][1] * n.y + m[2][1] * n.z,
+            z: m[0][2] * n.x + m[1][2] * n.y + m[2][2] * n.z,
+        }
+        .unit()
+    }
+
+    #[inline]
+    pub fn ray(&self, r: &Ray) -> Ray {
+        // the direction isn't normalized, so distances along the ray are kept
+        Ray { origin: self.point(r.origin), direction: self.vector(r.direction), time: r.time }
+    }
+
+    // Bounds the transformed corners of `b`.
+    pub fn bounding_box(&self, b: Aabb) -> Aabb {
+        let corners = (0..8).map(|i| {
+            let pick =
+                |bit: usize, axis: usize| if i & bit == 0 { b.min[axis] } else { b.max[axis] };
+            self.point(Vec3 { x: pick(1, 0), y: pick(2, 1), z: pick(4, 2) })
+        });
+        Aabb::from_points(corners)
+    }
+}
+
+// `self * b` applies `b` first.
+impl Mul<Transform> for Transform {
+    type Output = Transform;
+
+    fn mul(self, b: Transform) -> Self::Output {
+        Transform { m: self.m * b.m, inv: b.inv * self.inv }
+    }
+}
+
+// Places a shape in the scene with a transform from its object space to world
+// space. Wrapping an `Arc` instances the shape without copying it.
+#[derive(Clone, Debug)]
+pub struct Transformed<S> {
+    pub shape: S,
+    pub transform: Transform,
+}
+
+impl<S: Shape> Transformed<S> {
+    pub fn new(shape: S, transform: Transform) -> Self { Transformed { shape, transform } }
+
+    fn to_world<'a>(&self, rec: HitRecord<'a>) -> HitRecord<'a> {
+        let t = &self.transform;
+        // the inverse transpose keeps the normals facing the transformed ray
+        HitRecord {
+            point: t.point(rec.point),
+            normal: t.normal(rec.normal),
+            geometric_normal: t.normal(rec.geometric_normal),
+            dpdu: t.vector(rec.dpdu),
+            dpdv: t.vector(rec.dpdv),
+            ..rec
+        }
+    }
+}
+
+impl<S: Shape> Shape for Transformed<S> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let rec = self.shape.hit(&self.transform.inverse().ray(ray), t_min, t_max)?;
+        Some(self.to_world(rec))
+    }
+
+    fn sample_hit(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        rng: &mut Rng,
+    ) -> Option<HitRecord<'_>> {
+        let rec = self.shape.sample_hit(&self.transform.inverse().ray(ray), t_min, t_max, rng)?;
+        Some(self.to_world(rec))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.shape.bounding_box().map(|b| self.transform.bounding_box(b))
+    }
+}
@@ -1,4 +1,4 @@
-use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::ops::{Add, Div, Index, Mul, Neg, Sub};
 
 #[derive(Clone, Copy, Debug)]
 pub struct Vec3 {
@@ -28,6 +28,19 @@ impl Vec3 {
     #[inline]
     pub fn dot(&self, a: Vec3) -> f32 { self.x * a.x + self.y * a.y + self.z * a.z }
 
+    #[inline]
+    pub fn min(&self, a: Vec3) -> Vec3 {
+        Vec3 { x: self.x.min(a.x), y: self.y.min(a.y), z: self.z.min(a.z) }
+    }
+
+    #[inline]
+    pub fn max(&self, a: Vec3) -> Vec3 {
+        Vec3 { x: self.x.max(a.x), y: self.y.max(a.y), z: self.z.max(a.z) }
+    }
+
+    #[inline]
+    pub fn max_component(&self) -> f32 { self.x.max(self.y).max(self.z) }
+
     #[inline]
     pub fn cross(&self, a: Vec3) -> Vec3 {
         Vec3 {
@@ -36,6 +49,37 @@ impl Vec3 {
             z: self.x * a.y - self.y * a.x,
         }
     }
+
+    // Two unit vectors perpendicular to this unit vector and to each other.
+    pub fn orthonormal_basis(self) -> (Vec3, Vec3) {
+        let a = if self.x.abs() > 0.9 {
+            Vec3 { x: 0., y: 1., z: 0. }
+        } else {
+            Vec3 { x: 1., y: 0., z: 0. }
+        };
+        let v = self.cross(a).unit();
+        let u = self.cross(v);
+        (u, v)
+    }
+}
+
+impl From<[f32; 3]> for Vec3 {
+    #[inline]
+    fn from([x, y, z]: [f32; 3]) -> Self { Vec3 { x, y, z } }
+}
+
+impl Index<usize> for Vec3 {
+    type Output = f32;
+
+    #[inline]
+    fn index(&self, axis: usize) -> &Self::Output {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vec3 axis out of range: {axis}"),
+        }
+    }
 }
 
 impl Add<Vec3> for Vec3 {
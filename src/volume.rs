@@ -0,0 +1,141 @@
+use std::{
+    fs::File,
+    io::{self, ErrorKind, Read},
+    path::Path,
+};
+
+use crate::vec::Vec3;
+
+// A dense grid of densities covering the unit cube, with cell centers at
+// half-integer coordinates. Stored as x varying fastest, then y, then z.
+#[derive(Clone, Debug)]
+pub struct VoxelGrid {
+    size: [usize; 3],
+    densities: Vec<f32>,
+    max: f32,
+}
+
+impl VoxelGrid {
+    pub fn new(size: [usize; 3], densities: Vec<f32>) -> VoxelGrid {
+        assert_eq!(densities.len(), size[0] * size[1] * size[2]);
+        let max = densities.iter().copied().fold(0., f32::max);
+        VoxelGrid { size, densities, max }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<VoxelGrid> {
+        VoxelGrid::from_reader(File::open(path)?)
+    }
+
+    // Reads a text header line `VOXELS <x> <y> <z>` followed by as many raw
+    // little-endian f32 values.
+    pub fn from_reader<R: Read>(mut reader: R) -> io::Result<VoxelGrid> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let invalid = |message: &str| io::Error::new(ErrorKind::InvalidData, message.to_owned());
+
+        let header_end = bytes.iter().position(|&b| b == b'\n').ok_or(invalid("missing header"))?;
+        let header =
+            std::str::from_utf8(&bytes[..header_end]).map_err(|_| invalid("bad header"))?;
+        let mut fields = header.split_whitespace();
+        if fields.next() != Some("VOXELS") {
+            return Err(invalid("not a voxel grid"));
+        }
+        let mut size = [0; 3];
+        for s in &mut size {
+            *s = match fields.next().map(str::parse) {
+                Some(Ok(n)) if n > 0 => n,
+                _ => return Err(invalid("bad grid size")),
+            };
+        }
+
+        let len = size.iter().try_fold(4usize, |len, &n| len.checked_mul(n));
+        let len = len.ok_or(invalid("bad grid size"))?;
+        let data = &bytes[header_end + 1..];
+        if data.len() != len {
+            return Err(invalid("data doesn't match the grid size"));
+        }
+        let densities = data
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]).max(0.))
+            .collect();
+        Ok(VoxelGrid::new(size, densities))
+    }
+
+    pub fn max(&self) -> f32 { self.max }
+
+    fn voxel(&self, x: isize, y: isize, z: isize) -> f32 {
+        let clamp = |i: isize, n: usize| i.clamp(0, n as isize - 1) as usize;
+        let (x, y, z) = (clamp(x, self.size[0]), clamp(y, self.size[1]), clamp(z, self.size[2]));
+        self.densities[(z * self.size[1] + y) * self.size[0] + x]
+    }
+
+    // Trilinearly interpolated density at `p` in the unit cube, 0 outside of it.
+    pub fn density(&self, p: Vec3) -> f32 {
+        if !(0. ..=1.).contains(&p.x) || !(0. ..=1.).contains(&p.y) || !(0. ..=1.).contains(&p.z) {
+            return 0.;
+        }
+
+        let g = Vec3 {
+            x: p.x * self.size[0] as f32 - 0.5,
+            y: p.y * self.size[1] as f32 - 0.5,
+            z: p.z * self.size[2] as f32 - 0.5,
+        };
+        let cell = g.map(f32::floor);
+        let f = g - cell;
+        let (x, y, z) = (cell.x as isize, cell.y as isize, cell.z as isize);
+
+        let mut sum = 0.;
+        for (i, j, k) in (0..8).map(|c| (c & 1, (c >> 1) & 1, c >> 2)) {
+            let weight = (if i == 0 { 1. - f.x } else { f.x })
+                * (if j == 0 { 1. - f.y } else { f.y })
+                * (if k == 0 { 1. - f.z } else { f.z });
+            sum += weight * self.voxel(x + i, y + j, z + k);
+        }
+        sum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(header: &str, densities: &[f32]) -> io::Result<VoxelGrid> {
+        let mut bytes = header.as_bytes().to_vec();
+        bytes.extend(densities.iter().flat_map(|d| d.to_le_bytes()));
+        VoxelGrid::from_reader(&bytes[..])
+    }
+
+    fn error_message(result: io::Result<VoxelGrid>) -> String {
+        let err = result.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        err.to_string()
+    }
+
+    #[test]
+    fn loads_densities() {
+        let grid = read("VOXELS 2 1 1\n", &[0.5, 2.]).unwrap();
+        assert_eq!(grid.size, [2, 1, 1]);
+        assert_eq!(grid.max(), 2.);
+        // cell centers are at a quarter and three quarters along x
+        assert_eq!(grid.density(Vec3 { x: 0.25, y: 0.5, z: 0.5 }), 0.5);
+        assert_eq!(grid.density(Vec3 { x: 0.75, y: 0.5, z: 0.5 }), 2.);
+    }
+
+    #[test]
+    fn rejects_data_not_matching_the_size() {
+        let result = read("VOXELS 2 2 1\n", &[0.5, 2., 1.]);
+        assert_eq!(error_message(result), "data doesn't match the grid size");
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let result = read("VOXEL 1 1 1\n", &[1.]);
+        assert_eq!(error_message(result), "not a voxel grid");
+        let result = read("VOXELS 1 0 1\n", &[]);
+        assert_eq!(error_message(result), "bad grid size");
+        let result = read("VOXELS 4294967296 4294967296 2\n", &[]);
+        assert_eq!(error_message(result), "bad grid size");
+        let result = read("VOXELS 1 1", &[]);
+        assert_eq!(error_message(result), "missing header");
+    }
+}